@@ -0,0 +1,154 @@
+//! Inspecting audio files, mostly with `ffprobe` (part of FFmpeg).
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+
+/// Technical details of an audio file.
+#[derive(Debug, Serialize)]
+pub struct AudioInfo {
+    /// Container format, e.g. `mov,mp4,m4a,3gp,3g2,mj2` for `.m4a` files.
+    pub format: String,
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+    /// Bits per second.
+    pub bitrate: Option<u64>,
+    /// Length in seconds.
+    pub duration_secs: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    format: ProbeFormat,
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    format_name: String,
+    bit_rate: Option<String>,
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+    bit_rate: Option<String>,
+}
+
+/// Runs `ffprobe` (the executable at `ffprobe`) on the file.
+pub fn info(ffprobe: &Path, path: &Path) -> Result<AudioInfo, String> {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(path)
+        .output()
+        .map_err(|err| format!("Couldn't run {}: {err}", ffprobe.display()))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let probe: Probe = serde_json::from_slice(&output.stdout)
+        .map_err(|err| format!("Couldn't parse ffprobe output: {err}"))?;
+    let stream = probe
+        .streams
+        .into_iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("audio"));
+    let stream_bitrate = stream.as_ref().and_then(|stream| stream.bit_rate.clone());
+    Ok(AudioInfo {
+        duration_secs: probe.format.duration.and_then(|secs| secs.parse().ok()),
+        format: probe.format.format_name,
+        codec: stream.as_ref().and_then(|stream| stream.codec_name.clone()),
+        sample_rate: stream
+            .as_ref()
+            .and_then(|stream| stream.sample_rate.as_deref()?.parse().ok()),
+        channels: stream.as_ref().and_then(|stream| stream.channels),
+        bitrate: stream_bitrate
+            .or(probe.format.bit_rate)
+            .and_then(|bitrate| bitrate.parse().ok()),
+    })
+}
+
+/// Checks that the file starts like its extension says. Files with extensions we don't
+/// know the format of are assumed to be fine.
+pub fn has_valid_header(path: &Path) -> io::Result<bool> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut header = Vec::with_capacity(12);
+    File::open(path)?.take(12).read_to_end(&mut header)?;
+    let valid = match extension.as_str() {
+        "m4a" | "mp4" | "mov" => header.get(4..8) == Some(b"ftyp"),
+        "wav" => header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE"),
+        "mp3" => header.starts_with(b"ID3") || header.starts_with(&[0xFF]),
+        "ogg" | "opus" => header.starts_with(b"OggS"),
+        "flac" => header.starts_with(b"fLaC"),
+        "caf" => header.starts_with(b"caff"),
+        _ => true,
+    };
+    Ok(valid)
+}
+
+/// Runs the file through an FFmpeg audio filter (`-af`) and writes the result to
+/// `output`, keeping the metadata. The format is picked by the extension of `output`.
+pub fn filter(
+    ffmpeg: &Path,
+    input: &Path,
+    output: &Path,
+    filter: &str,
+) -> Result<(), String> {
+    let result = Command::new(ffmpeg)
+        .args(["-v", "error", "-y", "-i"])
+        .arg(input)
+        .args(["-map_metadata", "0", "-af", filter])
+        .arg(output)
+        .output()
+        .map_err(|err| format!("Couldn't run {}: {err}", ffmpeg.display()))?;
+    if !result.status.success() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Runs a transcription command (split at whitespace) with the file as the last argument,
+/// and returns what it printed, trimmed.
+pub fn transcribe(command: &str, path: &Path) -> Result<String, String> {
+    let mut args = command.split_whitespace();
+    let program = args.next().ok_or("The transcription command is empty")?;
+    let result = Command::new(program)
+        .args(args)
+        .arg(path)
+        .output()
+        .map_err(|err| format!("Couldn't run {program}: {err}"))?;
+    if !result.status.success() {
+        return Err(format!(
+            "{program} failed ({}): {}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    let text = String::from_utf8_lossy(&result.stdout).trim().to_string();
+    if text.is_empty() {
+        return Err(format!("{program} printed no transcript"));
+    }
+    Ok(text)
+}
@@ -0,0 +1,89 @@
+//! Settings that come from the environment (including `.env`).
+//!
+//! They are read once into [`Config`], which lives in the app state, so that commands see
+//! the same values and `reload_config` can change them without restarting the app.
+
+use crate::storage;
+use std::env;
+use std::path::PathBuf;
+
+/// Where VLC is unless `VLC_PATH` says otherwise.
+const DEFAULT_VLC_PATH: &str = "/Applications/VLC.app/Contents/MacOS/VLC";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `VOICE_MEMOS_STORAGE`, or the error to report if it's not set.
+    storage_dir: Result<PathBuf, String>,
+    /// The VLC executable (`VLC_PATH`).
+    pub vlc_path: PathBuf,
+    /// The FFmpeg executable (`FFMPEG_PATH`), looked up in `PATH` by default.
+    pub ffmpeg_path: PathBuf,
+    /// The `ffprobe` executable, which comes with FFmpeg and is next to `ffmpeg_path`.
+    pub ffprobe_path: PathBuf,
+    /// The command for editing transcripts (`MEMOS_EDITOR`, or else `EDITOR`), e.g.
+    /// `code --wait`. Split at whitespace, so the program path can't contain spaces.
+    pub editor: Option<String>,
+    /// The command for transcribing a memo (`MEMOS_TRANSCRIBE_COMMAND`), split at
+    /// whitespace like `editor`. It gets the audio file as the last argument and should
+    /// print the transcript.
+    pub transcribe_command: Option<String>,
+    /// JSON file mapping keywords to labels for `suggest_label` (`MEMOS_LABEL_KEYWORDS`).
+    pub label_keywords_path: Option<PathBuf>,
+    /// File with the stopwords for `top_words` (`MEMOS_STOPWORDS`).
+    pub stopwords_path: Option<PathBuf>,
+    /// Needed for changing existing to-dos in Things (`THINGS_AUTH_TOKEN`).
+    pub things_auth_token: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let ffmpeg_path = env::var_os("FFMPEG_PATH")
+            .filter(|path| !path.is_empty())
+            .map_or_else(|| PathBuf::from("ffmpeg"), PathBuf::from);
+        let mut ffprobe_path = ffmpeg_path.with_file_name("ffprobe");
+        if let Some(extension) = ffmpeg_path.extension() {
+            ffprobe_path.set_extension(extension);
+        }
+        Config {
+            storage_dir: storage::dir(),
+            vlc_path: env::var_os("VLC_PATH")
+                .filter(|path| !path.is_empty())
+                .map_or_else(|| PathBuf::from(DEFAULT_VLC_PATH), PathBuf::from),
+            ffmpeg_path,
+            ffprobe_path,
+            editor: ["MEMOS_EDITOR", "EDITOR"]
+                .into_iter()
+                .filter_map(|var| env::var(var).ok())
+                .find(|editor| !editor.trim().is_empty()),
+            transcribe_command: env::var("MEMOS_TRANSCRIBE_COMMAND")
+                .ok()
+                .filter(|command| !command.trim().is_empty()),
+            label_keywords_path: env::var_os("MEMOS_LABEL_KEYWORDS").map(PathBuf::from),
+            stopwords_path: env::var_os("MEMOS_STOPWORDS").map(PathBuf::from),
+            things_auth_token: env::var("THINGS_AUTH_TOKEN").ok(),
+        }
+    }
+
+    /// Returns the directory with the audio files.
+    pub fn storage_dir(&self) -> Result<PathBuf, String> {
+        self.storage_dir.clone()
+    }
+}
+
+/// Sets the variables from `.env` that aren't set in the environment already, like
+/// `dotenvy::dotenv`. First unsets `previous`, the variables that the last call set, so
+/// that a variable removed from `.env` goes away. Returns the variables it set.
+pub fn load_env_file(previous: &[String]) -> dotenvy::Result<Vec<String>> {
+    let vars = dotenvy::dotenv_iter()?.collect::<dotenvy::Result<Vec<_>>>()?;
+    for key in previous {
+        env::remove_var(key);
+    }
+    let mut set = Vec::new();
+    for (key, value) in vars {
+        if env::var_os(&key).is_none() {
+            env::set_var(&key, value);
+            set.push(key);
+        }
+    }
+    Ok(set)
+}
@@ -0,0 +1,390 @@
+//! Database schema and migrations.
+//!
+//! The `memos` table is created by the import script as `(name, content, label)`. On
+//! startup we check that it's there and add any missing columns, so that commands don't
+//! fail later with cryptic errors from `row.get`.
+
+use crate::storage;
+use rusqlite::Connection;
+use std::fmt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Columns that are created if missing, with their declarations. `name` isn't here
+/// because without it there's no way to match memos with their files.
+const COLUMNS: &[(&str, &str)] = &[
+    ("content", "TEXT NOT NULL DEFAULT ''"),
+    ("label", "TEXT"),
+    ("pushed_to_things", "TEXT"),
+    ("updated_at", "TEXT"),
+    ("favorite", "INTEGER NOT NULL DEFAULT 0"),
+    ("merged_from", "TEXT"),
+    ("created_at", "TEXT"),
+    ("playback_position_secs", "REAL"),
+    ("content_hash", "TEXT"),
+    ("moved_to", "TEXT"),
+];
+
+#[derive(Debug)]
+pub enum SchemaError {
+    MissingTable,
+    MissingColumn(&'static str),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::MissingTable => write!(
+                f,
+                "the database has no `memos` table, run `import-memos` to create it"
+            ),
+            SchemaError::MissingColumn(column) => {
+                write!(f, "the `memos` table has no `{column}` column")
+            }
+            SchemaError::Sqlite(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SchemaError {
+    fn from(err: rusqlite::Error) -> Self {
+        SchemaError::Sqlite(err)
+    }
+}
+
+/// Supplies the SQLCipher key and checks that it can decrypt the database.
+#[cfg(feature = "sqlcipher")]
+pub fn unlock(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "key", key)?;
+    // SQLCipher only reports a wrong key once the database is actually read.
+    conn.query_row("SELECT count(*) FROM sqlite_master", (), |_| Ok(()))
+}
+
+/// Checks that the `memos` table is usable and brings it up to date. With `read_only`,
+/// what's missing is only stood in for, see [`stand_in_for_missing`].
+pub fn migrate(conn: &Connection, read_only: bool) -> Result<(), SchemaError> {
+    let existing = columns(conn)?;
+    if existing.is_empty() {
+        return Err(SchemaError::MissingTable);
+    }
+    let has = |name: &str| existing.iter().any(|(column, _)| column == name);
+    if !has("name") {
+        return Err(SchemaError::MissingColumn("name"));
+    }
+    if read_only {
+        return Ok(stand_in_for_missing(conn, &existing)?);
+    }
+    for (name, decl) in COLUMNS {
+        if !has(name) {
+            conn.execute(&format!("ALTER TABLE memos ADD COLUMN {name} {decl}"), ())?;
+        }
+    }
+    if !has("created_at") {
+        backfill_created_at(conn)?;
+    }
+    if !has("content_hash") {
+        recompute_content_hashes(conn, true)?;
+    }
+    if !create_name_index(conn)? {
+        eprintln!("Several memos have the same name, run fix_names to rename them");
+    }
+    // The import script doesn't know about `created_at`, so fill it in on insert.
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS memos_created_at AFTER INSERT ON memos
+             WHEN new.created_at IS NULL BEGIN
+             UPDATE memos SET created_at = datetime('now') WHERE rowid = new.rowid;
+         END",
+        (),
+    )?;
+    // The app sets `content_hash` together with the content. When something else (like
+    // the import script) changes the content, the hash is cleared instead of going stale.
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS memos_content_hash AFTER UPDATE OF content ON memos
+             WHEN new.content IS NOT old.content AND new.content_hash IS old.content_hash
+             BEGIN
+             UPDATE memos SET content_hash = NULL WHERE rowid = new.rowid;
+         END",
+        (),
+    )?;
+    if !has_search_index(conn)? {
+        rebuild_search_index(conn)?;
+    }
+    create_bookmarks(conn)?;
+    create_history(conn)?;
+    Ok(())
+}
+
+/// Lets a read-only database that was never opened by this version of the app be
+/// browsed, by putting temporary stand-ins (which don't need writing to the database
+/// file) in front of what's missing: a `memos` view with the missing columns set to
+/// their defaults, a search index built in memory, and empty bookmarks and history.
+fn stand_in_for_missing(
+    conn: &Connection,
+    existing: &[(String, String)],
+) -> rusqlite::Result<()> {
+    let has = |name: &str| existing.iter().any(|(column, _)| column == name);
+    if COLUMNS.iter().any(|(name, _)| !has(name)) {
+        let mut select: Vec<String> = vec![String::from("rowid AS rowid")];
+        select.extend(existing.iter().map(|(name, _)| format!("\"{name}\"")));
+        for (name, decl) in COLUMNS.iter().filter(|(name, _)| !has(name)) {
+            let default = decl
+                .split_once(" DEFAULT ")
+                .map_or("NULL", |(_, value)| value);
+            select.push(format!("{default} AS {name}"));
+        }
+        conn.execute(
+            &format!(
+                "CREATE TEMP VIEW memos AS SELECT {} FROM main.memos",
+                select.join(", ")
+            ),
+            (),
+        )?;
+    }
+    if !has_search_index(conn)? {
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE temp.memos_fts USING fts5(name, content);
+             INSERT INTO temp.memos_fts (rowid, name, content)
+                 SELECT rowid, name, content FROM memos;",
+        )?;
+    }
+    if !has_table(conn, "bookmarks")? {
+        conn.execute(
+            &format!("CREATE TEMP TABLE bookmarks ({BOOKMARKS_COLUMNS})"),
+            (),
+        )?;
+    }
+    if !has_table(conn, "memo_history")? {
+        conn.execute(
+            &format!("CREATE TEMP TABLE memo_history ({HISTORY_COLUMNS})"),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+/// Makes memo names unique, since they identify memos everywhere. Returns false (and
+/// leaves the table as is) if some names are taken by several memos already.
+pub fn create_name_index(conn: &Connection) -> rusqlite::Result<bool> {
+    match conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS memos_name ON memos (name)",
+        (),
+    ) {
+        Ok(_) => Ok(true),
+        Err(err)
+            if err.sqlite_error_code()
+                == Some(rusqlite::ErrorCode::ConstraintViolation) =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Creates the table with previous contents of memos, and the triggers that fill it
+/// whenever the content changes and keep it in sync when memos are renamed or deleted.
+fn create_history(conn: &Connection) -> rusqlite::Result<()> {
+    if has_table(conn, "memo_history")? {
+        for column in HISTORY_ADDED_COLUMNS {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM pragma_table_info('memo_history') \
+                 WHERE name = ?1)",
+                [column],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                conn.execute(
+                    &format!("ALTER TABLE memo_history ADD COLUMN {column} TEXT"),
+                    (),
+                )?;
+            }
+        }
+    }
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS memo_history ({HISTORY_COLUMNS});
+         CREATE INDEX IF NOT EXISTS memo_history_name ON memo_history (name);
+         CREATE TRIGGER IF NOT EXISTS memo_history_update AFTER UPDATE OF content ON memos
+             WHEN new.content IS NOT old.content BEGIN
+             INSERT INTO memo_history (name, content) VALUES (new.name, old.content);
+         END;
+         CREATE TRIGGER IF NOT EXISTS memo_history_rename AFTER UPDATE OF name ON memos
+             BEGIN
+             UPDATE memo_history SET name = new.name WHERE name = old.name;
+         END;
+         CREATE TRIGGER IF NOT EXISTS memo_history_delete AFTER DELETE ON memos BEGIN
+             DELETE FROM memo_history WHERE name = old.name;
+         END;"
+    ))
+}
+
+/// Hashes memo content for `content_hash`, as 16 hex digits of its XXH3 hash.
+pub fn content_hash(content: &str) -> String {
+    format!("{:016x}", xxhash_rust::xxh3::xxh3_64(content.as_bytes()))
+}
+
+/// Sets `content_hash` of memos that don't have one, or of all memos unless
+/// `only_missing`. Returns how many memos were updated.
+pub fn recompute_content_hashes(
+    conn: &Connection,
+    only_missing: bool,
+) -> rusqlite::Result<usize> {
+    let filter = if only_missing {
+        "content_hash IS NULL"
+    } else {
+        "1"
+    };
+    let mut select_stmt =
+        conn.prepare(&format!("SELECT rowid, content FROM memos WHERE {filter}"))?;
+    let contents = select_stmt
+        .query_map((), |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (rowid, content) in &contents {
+        conn.execute(
+            "UPDATE memos SET content_hash = ?1 WHERE rowid = ?2",
+            (content_hash(content), rowid),
+        )?;
+    }
+    Ok(contents.len())
+}
+
+fn has_search_index(conn: &Connection) -> rusqlite::Result<bool> {
+    has_table(conn, "memos_fts")
+}
+
+fn has_table(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+const BOOKMARKS_COLUMNS: &str = "name TEXT NOT NULL, secs REAL NOT NULL, label TEXT";
+
+/// `merged_from` is set for the contents that merging records, to the memo each one came
+/// from, so that they can be split up again, and `merged_label` to that memo's label.
+const HISTORY_COLUMNS: &str = "id INTEGER PRIMARY KEY, name TEXT NOT NULL, \
+     content TEXT NOT NULL, replaced_at TEXT NOT NULL DEFAULT (datetime('now')), \
+     merged_from TEXT, merged_label TEXT";
+
+/// Columns of `memo_history` that were added after it was created.
+const HISTORY_ADDED_COLUMNS: &[&str] = &["merged_from", "merged_label"];
+
+/// Creates the table with timestamps of interest in memos, and the triggers that keep it
+/// in sync when memos are renamed or deleted.
+fn create_bookmarks(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS bookmarks ({BOOKMARKS_COLUMNS});
+         CREATE INDEX IF NOT EXISTS bookmarks_name ON bookmarks (name);
+         CREATE TRIGGER IF NOT EXISTS bookmarks_rename AFTER UPDATE OF name ON memos BEGIN
+             UPDATE bookmarks SET name = new.name WHERE name = old.name;
+         END;
+         CREATE TRIGGER IF NOT EXISTS bookmarks_delete AFTER DELETE ON memos BEGIN
+             DELETE FROM bookmarks WHERE name = old.name;
+         END;"
+    ))
+}
+
+/// Sets `created_at` of existing memos to the modification time of their files, which is
+/// when they were recorded unless the files were edited. Memos without files are left
+/// alone.
+fn backfill_created_at(conn: &Connection) -> rusqlite::Result<()> {
+    let Ok(dir) = storage::dir() else {
+        return Ok(());
+    };
+    let mut select_stmt = conn.prepare("SELECT name FROM memos")?;
+    let names = select_stmt
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for name in names {
+        let modified = storage::resolve(&dir, &name)
+            .metadata()
+            .and_then(|metadata| metadata.modified());
+        let Ok(Ok(since_epoch)) = modified.map(|time| time.duration_since(UNIX_EPOCH))
+        else {
+            continue;
+        };
+        conn.execute(
+            "UPDATE memos SET created_at = datetime(?1, 'unixepoch') WHERE name = ?2",
+            (since_epoch.as_secs() as i64, &name),
+        )?;
+    }
+    Ok(())
+}
+
+/// Creates the full-text search index over `memos` and the triggers that keep it in
+/// sync, unless they exist already.
+fn create_search_index(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS memos_fts
+             USING fts5(name, content, content='memos', content_rowid='rowid');
+         CREATE TRIGGER IF NOT EXISTS memos_fts_insert AFTER INSERT ON memos BEGIN
+             INSERT INTO memos_fts (rowid, name, content)
+                 VALUES (new.rowid, new.name, new.content);
+         END;
+         CREATE TRIGGER IF NOT EXISTS memos_fts_delete AFTER DELETE ON memos BEGIN
+             INSERT INTO memos_fts (memos_fts, rowid, name, content)
+                 VALUES ('delete', old.rowid, old.name, old.content);
+         END;
+         CREATE TRIGGER IF NOT EXISTS memos_fts_update AFTER UPDATE ON memos BEGIN
+             INSERT INTO memos_fts (memos_fts, rowid, name, content)
+                 VALUES ('delete', old.rowid, old.name, old.content);
+             INSERT INTO memos_fts (rowid, name, content)
+                 VALUES (new.rowid, new.name, new.content);
+         END;",
+    )
+}
+
+/// Drops the full-text search index and builds it again from `memos`, e.g. after the
+/// database was modified by something that bypassed the triggers.
+pub fn rebuild_search_index(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DROP TABLE IF EXISTS memos_fts", ())?;
+    create_search_index(conn)?;
+    conn.execute("INSERT INTO memos_fts (memos_fts) VALUES ('rebuild')", ())?;
+    Ok(())
+}
+
+/// Returns names and declared types of the `memos` columns.
+pub fn columns(conn: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("PRAGMA table_info(memos)")?;
+    let columns = stmt
+        .query_map((), |row| Ok((row.get("name")?, row.get("type")?)))?
+        .collect();
+    columns
+}
+
+/// Moves everything from the write-ahead log into the database file and truncates the
+/// log, so that the file is complete on its own for backups. Does nothing unless the
+/// database is in WAL mode.
+pub fn checkpoint(conn: &Connection) -> rusqlite::Result<()> {
+    let mode: String = conn.query_row("PRAGMA journal_mode", (), |row| row.get(0))?;
+    if mode.eq_ignore_ascii_case("wal") {
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_| Ok(()))?;
+    }
+    Ok(())
+}
+
+/// Writes a compacted copy of the database to `path`, which mustn't exist yet. Unlike
+/// copying the file, this works while the database is in use and includes the
+/// write-ahead log.
+pub fn snapshot(conn: &Connection, path: &Path) -> rusqlite::Result<()> {
+    conn.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+    Ok(())
+}
+
+/// Runs `PRAGMA integrity_check`, or the faster `PRAGMA quick_check` that skips
+/// checking that indexes match the tables. Returns "ok" or one problem per line.
+pub fn integrity_check(conn: &Connection, quick: bool) -> rusqlite::Result<String> {
+    let pragma = if quick {
+        "quick_check"
+    } else {
+        "integrity_check"
+    };
+    let mut stmt = conn.prepare(&format!("PRAGMA {pragma}"))?;
+    let problems = stmt
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(problems.join("\n"))
+}
@@ -3,40 +3,479 @@
     windows_subsystem = "windows"
 )]
 
+mod audio;
+mod config;
+mod db;
+mod player;
+mod storage;
 mod things3;
 
-use rusqlite::{types::Value, Connection};
-use serde::Serialize;
+use player::{PlayOptions, Player};
+use rusqlite::{types::Value, Connection, OpenFlags, OptionalExtension, ToSql};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::env;
-use std::path::Path;
-use std::process::Command;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::{Arc, Mutex, OnceLock, RwLock},
 };
-use tauri::InvokeError;
+use tauri::Manager;
 use url::Url;
 // use tauri::{CustomMenuItem, Menu, MenuItem, Submenu};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Row {
     name: String,
     content: String,
     label: Option<String>,
+    /// When the memo was last sent to Things.
+    pushed_to_things: Option<String>,
+    /// When the content was last changed in the app.
+    updated_at: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    /// Names of the memos this one was merged from, comma-separated.
+    merged_from: Option<String>,
+    /// When the memo was recorded or created in the app, in UTC.
+    created_at: Option<String>,
+    /// Hash of the content for telling whether it changed, see [`db::content_hash`].
+    /// Missing if the content was changed outside the app.
+    content_hash: Option<String>,
+    /// Where the file was moved with [`move_file`], if it's not in the storage directory.
+    moved_to: Option<String>,
+}
+
+/// SQL expression for the label that is NULL if the label is missing, empty or "unknown",
+/// same as [`Row::known_label`].
+const KNOWN_LABEL: &str = "NULLIF(NULLIF(label, ''), 'unknown')";
+
+/// Whether a label says something about the memo, i.e. isn't empty or "unknown".
+fn is_known_label(label: &str) -> bool {
+    !label.is_empty() && label != "unknown"
+}
+
+impl Row {
+    /// Returns the label, unless it's missing, empty or "unknown".
+    fn known_label(&self) -> Option<&str> {
+        self.label.as_deref().filter(|label| is_known_label(label))
+    }
+
+    /// Returns the paths of the memo's audio files, see [`storage::memo_files`] and
+    /// [`storage::locate`].
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        storage::memo_files(&self.name, self.merged_from.as_deref())
+            .map(|file| storage::locate(dir, file, self.moved_to.as_deref()))
+            .collect()
+    }
+
+    /// Columns to select for [`Row::from_sql`].
+    const COLUMNS: &'static str =
+        "name, content, label, pushed_to_things, updated_at, favorite, merged_from, \
+         created_at, content_hash, moved_to";
+
+    fn from_sql(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Row {
+            name: row.get(0)?,
+            content: row.get(1)?,
+            label: row.get(2)?,
+            pushed_to_things: row.get(3)?,
+            updated_at: row.get(4)?,
+            favorite: row.get(5)?,
+            merged_from: row.get(6)?,
+            created_at: row.get(7)?,
+            content_hash: row.get(8)?,
+            moved_to: row.get(9)?,
+        })
+    }
+}
+
+/// Result of pushing memos to Things.
+#[derive(Debug, Serialize)]
+struct PushResult {
+    pushed: Vec<String>,
+    /// Memos that weren't pushed because they had already been pushed before.
+    skipped: Vec<String>,
+    /// Memos that couldn't be pushed, in best-effort mode.
+    failed: Vec<Failure>,
+}
+
+/// Result of a command that acts on several memos.
+#[derive(Debug, Default, Serialize)]
+struct BatchResult {
+    succeeded: Vec<String>,
+    /// Only filled in best-effort mode, otherwise the first failure aborts the command.
+    failed: Vec<Failure>,
+    /// Set if the command was stopped with [`cancel_operation`], to how many memos it
+    /// had got through.
+    cancelled_after: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Failure {
+    name: String,
+    reason: String,
+}
+
+/// How a selection of memos is mapped into Things.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+enum ThingsMode {
+    /// Each memo becomes its own to-do.
+    #[default]
+    SeparateTodos,
+    /// One to-do whose checklist items are the memo contents.
+    SingleTodoWithChecklist,
+    /// A project containing each memo as a to-do.
+    Project,
+}
+
+/// How a memo's label is represented in Things.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+enum LabelDestination {
+    #[default]
+    Ignore,
+    /// Tag the to-do with the label. Things only applies tags that already exist.
+    Tag,
+    /// Put the label into the to-do's notes.
+    NotePrefix,
+}
+
+/// How a memo's content is put into its to-do. Doesn't apply to
+/// [`ThingsMode::SingleTodoWithChecklist`], where each memo is a checklist item already.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+enum ContentMapping {
+    /// The content is the title.
+    #[default]
+    Title,
+    /// Each non-empty line is a checklist item.
+    ChecklistLines,
+    /// Each sentence is a checklist item.
+    ChecklistSentences,
+}
+
+/// Settings for converting memos into Things items.
+#[derive(Debug, Clone, Copy, Default)]
+struct ThingsOptions {
+    mode: ThingsMode,
+    label_as: LabelDestination,
+    content_as: ContentMapping,
+}
+
+/// Totals over the content of all memos.
+#[derive(Debug, Serialize)]
+struct ContentStats {
+    memos: usize,
+    characters: usize,
+    words: usize,
+    average_words: f64,
+}
+
+/// How many memos have audio files, and how much space the files take.
+#[derive(Debug, Default, Serialize)]
+struct StorageSummary {
+    memos: usize,
+    with_files: usize,
+    missing_files: usize,
+    bytes: u64,
+}
+
+/// A memo without a file of the same name, see [`name_file_mismatches`].
+#[derive(Debug, Serialize)]
+struct NameMismatch {
+    name: String,
+    /// Files without a memo whose names are close to the memo's name, best first.
+    suggestions: Vec<String>,
+}
+
+/// A database snapshot, see [`list_snapshots`].
+#[derive(Debug, Serialize)]
+struct Snapshot {
+    name: String,
+    /// When the snapshot was taken, in milliseconds since the Unix epoch.
+    created_at_millis: u64,
+    bytes: u64,
+}
+
+/// How many memos were created recently, see [`recent_counts`].
+#[derive(Debug, Serialize)]
+struct RecentCounts {
+    /// Since local midnight.
+    today: usize,
+    last_7_days: usize,
+    last_30_days: usize,
+}
+
+/// A memo with its content cut short, see [`load_previews`].
+#[derive(Debug, Serialize)]
+struct Preview {
+    #[serde(flatten)]
+    row: Row,
+    /// Whether the content was cut.
+    truncated: bool,
+}
+
+/// A line of [`diff`] output.
+#[derive(Debug, Serialize)]
+struct DiffLine {
+    kind: DiffKind,
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DiffKind {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+/// What to do with audio files that have no matching memo.
+#[derive(Debug, Clone, Copy, Deserialize)]
+enum OrphanAction {
+    /// Add a memo with empty content for each file.
+    Import,
+    /// Delete the files.
+    Delete,
+}
+
+/// What to do when an imported memo has the same name as an existing one.
+#[derive(Debug, Clone, Copy, Deserialize)]
+enum ConflictPolicy {
+    /// Keep the existing memo.
+    Skip,
+    /// Replace the existing memo.
+    Overwrite,
+    /// Import under a new name, e.g. `memo (2).m4a`.
+    Rename,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ImportSummary {
+    inserted: usize,
+    overwritten: usize,
+    renamed: usize,
+    skipped: usize,
+}
+
+/// Payload of the `things-progress` event.
+#[derive(Debug, Clone, Serialize)]
+struct ThingsProgress {
+    pushed: usize,
+    total: usize,
+}
+
+/// Payload of the `transcribe-progress` event.
+#[derive(Debug, Clone, Serialize)]
+struct TranscribeProgress {
+    /// The memo that was just transcribed, or failed to.
+    name: String,
+    done: usize,
+    total: usize,
+}
+
+/// A point of interest in a memo's recording.
+#[derive(Debug, Serialize)]
+struct Bookmark {
+    secs: f64,
+    label: Option<String>,
+}
+
+/// A previous content of a memo, see [`history`].
+#[derive(Debug, Serialize)]
+struct HistoryEntry {
+    id: i64,
+    content: String,
+    /// When this content was replaced, in UTC.
+    replaced_at: String,
+}
+
+/// Payload of the `command-timing` event.
+#[derive(Debug, Clone, Serialize)]
+struct CommandTiming {
+    command: String,
+    millis: f64,
+}
+
+/// How many invocations [`command_history`] remembers.
+const COMMAND_HISTORY_LEN: usize = 100;
+
+/// An entry of [`command_history`].
+#[derive(Debug, Clone, Serialize)]
+struct CommandLogEntry {
+    /// Tells apart calls of the same command.
+    id: u64,
+    command: String,
+    /// When the command was invoked, in milliseconds since the Unix epoch.
+    at_millis: u64,
+    /// Whether the command returned an error, or `None` while it's running.
+    failed: Option<bool>,
+}
+
+/// Tweaks to how memos are merged.
+#[derive(Debug, Default)]
+struct MergeOptions {
+    /// Drop lines that already occurred earlier in the merged content.
+    dedupe: bool,
+    /// Name for the merged memo instead of the joined names.
+    title: Option<String>,
+    /// Name the merged memo after the first few words of its content.
+    summarize: bool,
+    /// Put each memo's label as a `## label` heading above its content.
+    label_sections: bool,
+    /// Label for the merged memo. By default it's the first known label, or all of them
+    /// with `label_sections`.
+    label: Option<String>,
 }
 
+/// How many words of the content go into a generated merge title.
+const SUMMARY_WORDS: usize = 6;
+
+/// Memos with fewer words are too short for [`detect_language`].
+const LANGUAGE_MIN_WORDS: usize = 3;
+
 struct State {
     db_conn: Arc<Mutex<Connection>>,
+    /// Whether the `rarray` table-valued function is available, see [`names_filter`].
+    rarray: bool,
+    /// Set with `MEMOS_READONLY`; commands that modify memos fail with
+    /// [`Error::ReadOnly`].
+    read_only: bool,
+    /// Where the database is (`MEMOS_DB`).
+    db_path: PathBuf,
+    /// The last [`COMMAND_HISTORY_LEN`] command invocations, oldest first.
+    command_history: Mutex<VecDeque<CommandLogEntry>>,
+    /// The id of the next [`CommandLogEntry`].
+    next_command_id: AtomicU64,
+    /// For emitting events from [`State::run_command`]. Set when the app starts.
+    app: OnceLock<tauri::AppHandle>,
+    /// Settings from the environment, see [`reload_config`].
+    config: RwLock<config::Config>,
+    /// Player processes started by [`open`], see [`stop_playback`].
+    players: Mutex<Vec<Child>>,
+    /// Set by [`cancel_operation`] to stop a long-running command before its next memo.
+    cancel: AtomicBool,
+    /// The variables that were set from `.env`, see [`config::load_env_file`].
+    env_file_keys: Mutex<Vec<String>>,
+}
+
+impl State {
+    /// Returns the directory with the audio files.
+    fn storage_dir(&self) -> Result<PathBuf, Error> {
+        let config = self.config.read().map_err(tauri_error)?;
+        config.storage_dir().map_err(tauri_error)
+    }
+
+    fn vlc_path(&self) -> Result<PathBuf, Error> {
+        let config = self.config.read().map_err(tauri_error)?;
+        Ok(config.vlc_path.clone())
+    }
+
+    fn ffmpeg_path(&self) -> Result<PathBuf, Error> {
+        let config = self.config.read().map_err(tauri_error)?;
+        Ok(config.ffmpeg_path.clone())
+    }
+
+    fn ffprobe_path(&self) -> Result<PathBuf, Error> {
+        let config = self.config.read().map_err(tauri_error)?;
+        Ok(config.ffprobe_path.clone())
+    }
+
+    /// Remembers a player process, and forgets the ones that have exited.
+    fn track_player(&self, child: Child) -> Result<(), Error> {
+        let mut players = self.players.lock().map_err(tauri_error)?;
+        reap_players(&mut players);
+        players.push(child);
+        Ok(())
+    }
+
+    /// The directory with database snapshots, next to the database.
+    fn snapshots_dir(&self) -> PathBuf {
+        self.db_path.with_file_name("snapshots")
+    }
+
+    /// Runs the body of a command; every command returns through this. The call is
+    /// recorded for [`command_history`] while it runs, and once it's done, whether it
+    /// failed, and a `command-timing` event is emitted to help find out what's slow.
+    fn run_command<T>(
+        &self,
+        command: &str,
+        body: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let id = self.next_command_id.fetch_add(1, Ordering::Relaxed);
+        let at_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+        if let Ok(mut history) = self.command_history.lock() {
+            if history.len() == COMMAND_HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(CommandLogEntry {
+                id,
+                command: command.to_string(),
+                at_millis,
+                failed: None,
+            });
+        }
+        let start = Instant::now();
+        let result = body();
+        let timing = CommandTiming {
+            command: command.to_string(),
+            millis: start.elapsed().as_secs_f64() * 1000.0,
+        };
+        if let Ok(mut history) = self.command_history.lock() {
+            if let Some(entry) = history.iter_mut().find(|entry| entry.id == id) {
+                entry.failed = Some(result.is_err());
+            }
+        }
+        if let Some(app) = self.app.get() {
+            if let Err(err) = app.emit_all("command-timing", timing) {
+                eprintln!("Couldn't emit command timing: {err}");
+            }
+        }
+        result
+    }
+
+    /// Clears a cancellation left over from an earlier command. Called by commands that
+    /// check [`State::is_cancelled`], before they start.
+    fn begin_operation(&self) {
+        self.cancel.store(false, Ordering::SeqCst);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+}
+
+/// Forgets player processes that have exited, collecting their exit status.
+fn reap_players(players: &mut Vec<Child>) {
+    players.retain_mut(|player| matches!(player.try_wait(), Ok(None)));
 }
 
 fn main() {
+    // Hands `memo://` links over to the running instance, if there is one
+    tauri_plugin_deep_link::prepare("github.neongreen.memos");
+
     // Load the .env file
-    dotenvy::dotenv().expect("Failed to read .env file");
+    let env_file_keys = config::load_env_file(&[]).expect("Failed to read .env file");
 
-    let connection =
-        Connection::open(env::var("MEMOS_DB").expect("MEMOS_DB env var missing"))
-            .expect("Couldn't open database");
-    rusqlite::vtab::array::load_module(&connection).expect("Couldn't load array module");
+    let path = env::var("MEMOS_DB").expect("MEMOS_DB env var missing");
+    let read_only = env::var("MEMOS_READONLY").is_ok_and(|value| !value.is_empty());
+    let (connection, rarray) =
+        open_database(Path::new(&path), read_only).unwrap_or_else(|err| panic!("{err}"));
 
     // let menu = Menu::new();
 
@@ -44,243 +483,3903 @@ fn main() {
         // .menu(menu)
         .manage(State {
             db_conn: Arc::new(Mutex::from(connection)),
+            rarray,
+            read_only,
+            db_path: PathBuf::from(path),
+            command_history: Mutex::default(),
+            next_command_id: AtomicU64::default(),
+            app: OnceLock::new(),
+            config: RwLock::new(config::Config::from_env()),
+            players: Mutex::default(),
+            cancel: AtomicBool::default(),
+            env_file_keys: Mutex::new(env_file_keys),
         })
         .invoke_handler(tauri::generate_handler![
             load,
             kill,
             merge,
+            merge_and_push,
             set_content,
+            clear_content,
+            set_favorite,
+            load_favorites,
+            schema,
+            import,
+            play_and_push,
+            by_length,
+            name_index,
+            search,
+            rebuild_search_index,
             open,
             add_to_things,
+            things_url,
+            orphaned_files,
+            repair_orphaned_files,
+            content_stats,
+            play_label,
+            export_things_json,
+            unlabeled,
+            load_by_labels,
+            check_integrity,
+            on_date,
+            play_random,
+            append_to_things,
+            content_html,
+            move_file,
+            audio_info,
+            storage_summary,
+            unmerge,
+            suggest_label,
+            load_by_file_mtime,
+            wipe_all,
+            diff,
+            set_playback_position,
+            reveal_db,
+            auto_merge,
+            test_things,
+            activity,
+            load_previews,
+            replace_all,
+            replace_regex,
+            search_regex,
+            mark_pushed,
+            vocabulary,
+            top_words,
+            renumber,
+            command_history,
+            validate_names,
+            fix_names,
+            recompute_hashes,
+            share_text,
+            detect_language,
+            export_playlist,
+            set_labels_map,
+            add_bookmark,
+            list_bookmarks,
+            purge_older_than,
+            reload_config,
+            test_vlc,
+            backlinks,
+            normalize_labels,
+            history,
+            revert,
+            export_audio_zip,
+            broken_files,
+            merge_search_results,
+            asset_url,
+            recent_counts,
+            load_sidecar,
+            import_sidecars,
+            similarity,
+            similar_to,
+            stop_playback,
+            is_playing,
+            normalize_audio,
+            trim_silence,
+            not_in_labels,
+            create_snapshot,
+            list_snapshots,
+            restore_snapshot,
+            audit_labels,
+            edit_transcript_external,
+            export_taskpaper,
+            label_avg_length,
+            name_file_mismatches,
+            transcribe_all,
+            cancel_operation,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(|app| {
+            let _ = app.state::<State>().app.set(app.handle());
+            allow_storage_dir(&app.handle());
+            let handle = app.handle();
+            tauri_plugin_deep_link::register("memo", move |link| {
+                if let Err(err) = handle_memo_link(&handle, &link) {
+                    eprintln!("Couldn't handle {link}: {err}");
+                }
+            })?;
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let state = app.state::<State>();
+                if state.read_only {
+                    return;
+                }
+                let result = match state.db_conn.lock() {
+                    Ok(conn) => db::checkpoint(&conn).map_err(tauri_error),
+                    Err(err) => Err(tauri_error(err)),
+                };
+                if let Err(err) = result {
+                    eprintln!("Couldn't checkpoint the database on exit: {err}");
+                }
+            }
+        });
+}
+
+/// Opens the database, decrypts it with `MEMOS_DB_KEY` (with the `sqlcipher` feature),
+/// loads the array module and brings the schema up to date. Also returns whether the
+/// array module could be loaded, see [`State::rarray`].
+fn open_database(path: &Path, read_only: bool) -> Result<(Connection, bool), String> {
+    let connection = if read_only {
+        Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+    } else {
+        Connection::open(path)
+    }
+    .map_err(|err| format!("Couldn't open database: {err}"))?;
+    #[cfg(feature = "sqlcipher")]
+    if let Ok(key) = env::var("MEMOS_DB_KEY") {
+        db::unlock(&connection, &key).map_err(|err| {
+            format!("Couldn't decrypt database with MEMOS_DB_KEY: {err}")
+        })?;
+    }
+    let rarray = match rusqlite::vtab::array::load_module(&connection) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("Couldn't load array module, falling back to IN lists: {err}");
+            false
+        }
+    };
+    db::migrate(&connection, read_only)
+        .map_err(|err| format!("Couldn't set up the database: {err}"))?;
+    Ok((connection, rarray))
+}
+
+/// Handles `memo://play/<name>`, which plays the memo, and `memo://open/<name>`, which
+/// brings the app to the front and emits `select-memo` with the name.
+fn handle_memo_link(app: &tauri::AppHandle, link: &str) -> Result<(), Error> {
+    let url = Url::parse(link).map_err(tauri_error)?;
+    let name = percent_encoding::percent_decode_str(url.path().trim_start_matches('/'))
+        .decode_utf8()
+        .map_err(tauri_error)?;
+    let state = app.state::<State>();
+    {
+        let guard = state.db_conn.lock().map_err(tauri_error)?;
+        if !memo_exists(&guard, &name)? {
+            return Err(tauri_error(format!("No such memo: {}", name)));
+        }
+    }
+    match url.host_str() {
+        Some("play") => open(vec![&name], None, state).map(|_| ()),
+        Some("open") => {
+            let window = app
+                .get_window("main")
+                .ok_or_else(|| tauri_error("Main window is missing"))?;
+            window.set_focus().map_err(tauri_error)?;
+            window.emit("select-memo", name).map_err(tauri_error)
+        }
+        _ => Err(tauri_error(format!("Unknown memo link: {}", link))),
+    }
+}
+
+/// Error returned from commands. The frontend gets it as a message string.
+#[derive(Debug)]
+enum Error {
+    /// The database was locked by another connection, even after retrying.
+    Busy,
+    /// The app was started with `MEMOS_READONLY`.
+    ReadOnly,
+    /// A memo's file is missing.
+    NotFound(String),
+    InvalidRegex(regex::Error),
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Busy => f.write_str("The database is busy, try again in a moment"),
+            Error::ReadOnly => f.write_str("The database is open in read-only mode"),
+            Error::NotFound(path) => write!(f, "File {} doesn't exist", path),
+            Error::InvalidRegex(err) => write!(f, "Invalid regex: {}", err),
+            Error::Message(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
 }
 
-fn tauri_error<E>(error: E) -> InvokeError
+fn tauri_error<E>(error: E) -> Error
 where
-    E: std::fmt::Display,
+    E: fmt::Display,
 {
-    tauri::InvokeError::from(format!("{}", error))
+    Error::Message(format!("{}", error))
 }
 
-/// Loads data from the database
-#[tauri::command]
-fn load(state: tauri::State<State>) -> Result<Vec<Row>, InvokeError> {
-    let db_conn = state.db_conn.clone();
-    let guard = db_conn.lock().map_err(tauri_error)?;
-    let conn = &*guard;
-    let mut select_stmt = conn
-        .prepare("SELECT name, content, label FROM memos ORDER BY name ASC")
-        .map_err(tauri_error)?;
-    let mut rows_vec = Vec::new();
-    select_stmt
-        .query_and_then((), |row| {
-            rows_vec.push(Row {
-                name: row.get(0)?,
-                content: row.get(1)?,
-                label: row.get(2)?,
-            });
-            Ok::<(), rusqlite::Error>(())
-        })
-        .map_err(tauri_error)?
-        .for_each(drop);
-    Ok(rows_vec)
+/// Like [`tauri_error`], but turns busy and locked database errors into [`Error::Busy`],
+/// which [`retry_busy`] retries, and writes to a read-only database into
+/// [`Error::ReadOnly`].
+fn sqlite_error(error: rusqlite::Error) -> Error {
+    match error.sqlite_error_code() {
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+            Error::Busy
+        }
+        Some(rusqlite::ErrorCode::ReadOnly) => Error::ReadOnly,
+        _ => tauri_error(error),
+    }
 }
 
-/// Deletes rows with given names
-#[tauri::command]
-fn kill(names: Vec<&str>, state: tauri::State<State>) -> Result<(), InvokeError> {
-    let db_conn = state.db_conn.clone();
-    let guard = db_conn.lock().map_err(tauri_error)?;
-    let conn = &*guard;
-    let names_param = Rc::new(
-        names
-            .iter()
-            .copied()
-            .map(|s| Value::from(String::from(s)))
-            .collect::<Vec<Value>>(),
-    );
-    conn.execute("DELETE FROM memos WHERE name IN rarray(?1)", [names_param])
-        .map_err(tauri_error)?;
-    Ok(())
+/// How many times [`retry_busy`] tries before giving up.
+const BUSY_ATTEMPTS: u32 = 3;
+
+/// Runs `operation`, retrying with exponential backoff while it fails with
+/// [`Error::Busy`]. The operation should be atomic, e.g. a single statement or a
+/// transaction, so that retrying it doesn't apply anything twice.
+fn retry_busy<T>(mut operation: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+    let mut delay = Duration::from_millis(50);
+    for _ in 1..BUSY_ATTEMPTS {
+        match operation() {
+            Err(Error::Busy) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation()
 }
 
-/// Merges rows with given names into one row
-#[tauri::command]
-fn merge(names: Vec<&str>, state: tauri::State<State>) -> Result<(), InvokeError> {
-    let db_conn = state.db_conn.clone();
-    let guard = db_conn.lock().map_err(tauri_error)?;
-    let conn = &*guard;
-    let names_param = Rc::new(
+/// Converts names into a parameter usable with `rarray(?)`.
+fn names_param(names: &[&str]) -> Rc<Vec<Value>> {
+    Rc::new(
         names
             .iter()
-            .copied()
-            .map(|s| Value::from(String::from(s)))
-            .collect::<Vec<Value>>(),
-    );
-
-    if names.len() < 2 {
-        return Ok(());
-    }
+            .map(|&s| Value::from(String::from(s)))
+            .collect(),
+    )
+}
 
-    let mut rows_vec = Vec::new();
-    let mut select_stmt = conn
-        .prepare(
-            "SELECT name, content, label FROM memos WHERE name IN rarray(?1) ORDER BY name ASC",
+/// Builds an SQL condition checking that `column` is one of `names`, and its parameters.
+///
+/// Uses `rarray(?)` if the array module is loaded, and `IN (?, ?, ...)` otherwise. The
+/// placeholders are unnumbered, so any other parameters in the query should be too.
+fn names_filter(
+    column: &str,
+    names: &[&str],
+    rarray: bool,
+) -> (String, Vec<Box<dyn ToSql>>) {
+    if rarray {
+        (
+            format!("{} IN rarray(?)", column),
+            vec![Box::new(names_param(names))],
         )
-        .map_err(tauri_error)?;
-    select_stmt
-        .query_and_then([&names_param], |row| {
-            rows_vec.push(Row {
-                name: row.get(0)?,
-                content: row.get(1)?,
-                label: row.get(2)?,
-            });
-            Ok::<(), rusqlite::Error>(())
-        })
-        .map_err(tauri_error)?
-        .for_each(drop);
+    } else {
+        let placeholders = vec!["?"; names.len()].join(", ");
+        (
+            format!("{} IN ({})", column, placeholders),
+            names
+                .iter()
+                .map(|&name| Box::new(name.to_string()) as Box<dyn ToSql>)
+                .collect(),
+        )
+    }
+}
 
-    conn.execute("DELETE FROM memos WHERE name IN rarray(?1)", [&names_param])
-        .map_err(tauri_error)?;
+/// Selects rows with given names, ordered by name
+fn select_rows(
+    conn: &Connection,
+    names: &[&str],
+    rarray: bool,
+) -> Result<Vec<Row>, Error> {
+    let (filter, params) = names_filter("name", names, rarray);
+    query_rows(
+        conn,
+        &format!("{} ORDER BY name ASC", filter),
+        rusqlite::params_from_iter(params),
+    )
+}
 
-    let new_name = &rows_vec
-        .iter()
-        .map(|row| row.name.clone())
-        .collect::<Vec<_>>()
-        .join(",");
-    let new_content = &rows_vec
-        .iter()
-        .map(|row| row.content.clone())
-        .collect::<Vec<_>>()
-        .join("\n\n");
-    let new_label = &rows_vec
-        .iter()
-        .map(|row| row.label.clone())
-        .filter_map(|option| option)
-        .filter(|label| label != "unknown")
-        .next() // works like ".first"
-        .unwrap_or(String::from("unknown"));
+/// Deletes rows with given names
+fn delete_rows(conn: &Connection, names: &[&str], rarray: bool) -> Result<(), Error> {
+    let (filter, params) = names_filter("name", names, rarray);
     conn.execute(
-        "INSERT INTO memos (name, content, label) VALUES (?1, ?2, ?3)",
-        (new_name, new_content, new_label),
+        &format!("DELETE FROM memos WHERE {}", filter),
+        rusqlite::params_from_iter(params),
     )
-    .map_err(tauri_error)?;
-
+    .map_err(sqlite_error)?;
     Ok(())
 }
 
-/// Updates row content
-#[tauri::command]
-fn set_content(
-    name: &str,
-    new_content: &str,
-    state: tauri::State<State>,
-) -> Result<(), InvokeError> {
-    let db_conn = state.db_conn.clone();
-    let guard = db_conn.lock().map_err(tauri_error)?;
-    let conn = &*guard;
-    conn.execute(
-        "UPDATE memos SET content = ?1 WHERE name = ?2",
-        [new_content, name],
+/// Selects rows matching an SQL condition, e.g. `"favorite ORDER BY name ASC"`
+fn query_rows<P: rusqlite::Params>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> Result<Vec<Row>, Error> {
+    let mut select_stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM memos WHERE {}",
+            Row::COLUMNS,
+            condition
+        ))
+        .map_err(sqlite_error)?;
+    let rows = select_stmt
+        .query_map(params, Row::from_sql)
+        .map_err(sqlite_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(sqlite_error)?;
+    Ok(rows)
+}
+
+/// Selects the row with the given name
+fn select_row(conn: &Connection, name: &str) -> Result<Row, Error> {
+    conn.query_row(
+        &format!("SELECT {} FROM memos WHERE name = ?1", Row::COLUMNS),
+        [name],
+        Row::from_sql,
     )
-    .map_err(tauri_error)?;
-    Ok(())
+    .map_err(tauri_error)
 }
 
-/// Play audio files in the stored files directory, using VLC, and exit afterwards.
+/// Loads data from the database
 ///
-/// If there are several files, it will play all of them one after the other.
+/// If `require_file` is set, only memos whose audio files all exist are returned.
 #[tauri::command]
-fn open(names: Vec<&str>) -> Result<(), InvokeError> {
-    if !cfg!(target_os = "macos") {
-        return Err(tauri_error("This command is only available on macOS"));
-    }
-
-    let dir = env::var("VOICE_MEMOS_STORAGE").expect("VOICE_MEMOS_STORAGE not set");
-
-    // Detect if any of the files don't exist, and throw an error if so.
-    for file in &names {
-        let path = Path::new(&dir).join(file);
-        if !path.exists() {
-            return Err(tauri_error(format!(
-                "File {} doesn't exist",
-                path.display()
-            )));
+fn load(
+    require_file: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<Vec<Row>, Error> {
+    state.run_command("load", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let mut select_stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM memos ORDER BY name ASC",
+                Row::COLUMNS
+            ))
+            .map_err(tauri_error)?;
+        let mut rows_vec = Vec::new();
+        select_stmt
+            .query_and_then((), |row| {
+                rows_vec.push(Row::from_sql(row)?);
+                Ok::<(), rusqlite::Error>(())
+            })
+            .map_err(tauri_error)?
+            .for_each(drop);
+        if require_file.unwrap_or(false) {
+            let dir = state.storage_dir()?;
+            rows_vec.retain(|row| row.files(&dir).iter().all(|file| file.exists()));
         }
-    }
+        Ok(rows_vec)
+    })
+}
 
-    Command::new("/Applications/VLC.app/Contents/MacOS/VLC")
-        .current_dir(dir)
-        .args([vec!["--play-and-exit"], names].concat())
-        .spawn()
-        .map_err(tauri_error)?;
-    Ok(())
+/// Deletes rows with given names
+#[tauri::command]
+fn kill(names: Vec<&str>, state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("kill", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        retry_busy(|| delete_rows(&guard, &names, state.rarray))
+    })
 }
 
-/// Add to Things (Inbox).
+/// Merges rows with given names into one row
+///
+/// With `dedupe`, lines repeated across the memos are only kept once. With
+/// `label_sections`, each memo's content is headed by its label. See [`MergeOptions`]
+/// for the rest.
 #[tauri::command]
-fn add_to_things(
+fn merge(
     names: Vec<&str>,
+    dedupe: Option<bool>,
+    title: Option<String>,
+    summarize: Option<bool>,
+    label_sections: Option<bool>,
+    label: Option<String>,
     state: tauri::State<State>,
-) -> Result<(), InvokeError> {
-    // Detect if Things is available.
-    if !Path::new("/Applications/Things3.app").exists() {
-        return Err(tauri_error("Things is not installed"));
-    }
+) -> Result<(), Error> {
+    state.run_command("merge", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        if names.len() < 2 {
+            return Ok(());
+        }
+        let options = MergeOptions {
+            dedupe: dedupe.unwrap_or(false),
+            title,
+            summarize: summarize.unwrap_or(false),
+            label_sections: label_sections.unwrap_or(false),
+            label,
+        };
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            merge_rows(&tx, &names, &options, state.rarray)?;
+            tx.commit().map_err(sqlite_error)
+        })
+    })
+}
 
-    // Get memo contents from the database.
-    let db_conn = state.db_conn.clone();
-    let guard = db_conn.lock().map_err(tauri_error)?;
-    let conn = &*guard;
-    let names_param = Rc::new(
-        names
-            .iter()
-            .copied()
-            .map(|s| Value::from(String::from(s)))
-            .collect::<Vec<Value>>(),
-    );
-    let mut rows_vec = Vec::new();
-    let mut select_stmt = conn
-        .prepare(
-            "SELECT name, content, label FROM memos WHERE name IN rarray(?1) ORDER BY name ASC",
-        )
-        .map_err(tauri_error)?;
-    select_stmt
-        .query_and_then([&names_param], |row| {
-            rows_vec.push(Row {
-                name: row.get(0)?,
-                content: row.get(1)?,
-                label: row.get(2)?,
-            });
-            Ok::<(), rusqlite::Error>(())
+/// Merges rows with given names and sends the merged memo to Things as one to-do
+#[tauri::command]
+fn merge_and_push(names: Vec<&str>, state: tauri::State<State>) -> Result<Row, Error> {
+    state.run_command("merge_and_push", || {
+        state.check_writable()?;
+        check_things_installed()?;
+        if names.len() < 2 {
+            return Err(tauri_error("Select at least two memos to merge"));
+        }
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        // The merge is only kept once the memo is in Things.
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            let merged = merge_rows(&tx, &names, &MergeOptions::default(), state.rarray)?;
+            push_to_things(
+                &tx,
+                std::slice::from_ref(&merged),
+                ThingsOptions::default(),
+                state.rarray,
+                None,
+            )?;
+            let merged = select_row(&tx, &merged.name)?;
+            tx.commit().map_err(sqlite_error)?;
+            Ok(merged)
         })
-        .map_err(tauri_error)?
-        .for_each(drop);
+    })
+}
 
-    // Construct an array of things3::Item objects from the memos. Note the definition of Item: it's an enum with ItemTodo(Todo), and the Todo inside is a struct. Also, don't use mutability when creating a struct.
-    let mut items: Vec<things3::Item> = Vec::new();
+/// Replaces rows with given names by a single merged row, and returns it.
+fn merge_rows(
+    conn: &Connection,
+    names: &[&str],
+    options: &MergeOptions,
+    rarray: bool,
+) -> Result<Row, Error> {
+    let rows_vec = select_rows(conn, names, rarray)?;
+    // What `unmerge` splits the new memo into: the contents of the memos, or for memos
+    // that were merged themselves, the contents they were merged from.
+    let mut parts = Vec::new();
     for row in &rows_vec {
-        items.push(things3::Item::Todo(things3::Todo {
-            title: row.content.clone(),
-            notes: None,
-        }));
+        let sources = row.merged_from.as_deref().unwrap_or(&row.name);
+        let recorded = merged_parts(conn, &row.name)?;
+        if recorded
+            .iter()
+            .map(|part| part.name.as_str())
+            .eq(sources.split(','))
+        {
+            parts.extend(recorded);
+        } else {
+            parts.push(MergedPart {
+                name: sources.to_string(),
+                content: row.content.clone(),
+                label: row.label.clone(),
+            });
+        }
     }
+    delete_rows(conn, names, rarray)?;
 
-    // Add to Things, using things:///json. For now we won't remove the memos from the database - it seems too risky.
-    // TODO: I can use x-success to check that the things were added, and then it would be fine to remove them from the DB.
-    let mut url = Url::parse("things:///json").unwrap();
-    url.set_query(Some(&format!(
-        "data={}",
-        serde_json::to_string(&items).unwrap()
-    )));
-    url.query_pairs_mut()
-        .append_pair("reveal", &true.to_string());
-    Command::new("open")
-        .arg(url.as_str())
-        .spawn()
-        .map_err(tauri_error)?;
-
-    Ok(())
+    let merged_from = rows_vec
+        .iter()
+        .map(|row| row.merged_from.clone().unwrap_or_else(|| row.name.clone()))
+        .collect::<Vec<_>>()
+        .join(",");
+    let contents = rows_vec
+        .iter()
+        .map(|row| match row.known_label() {
+            Some(label) if options.label_sections => {
+                format!("## {}\n{}", label, row.content)
+            }
+            _ => row.content.clone(),
+        })
+        .collect();
+    let new_content = &if options.dedupe {
+        dedupe_lines(contents)
+    } else {
+        contents
+    }
+    .join("\n\n");
+    let mut known_labels: Vec<&str> =
+        rows_vec.iter().filter_map(Row::known_label).collect();
+    let mut seen = HashSet::new();
+    known_labels.retain(|label| seen.insert(*label));
+    let new_label = &match &options.label {
+        Some(label) => label.clone(),
+        None if options.label_sections && !known_labels.is_empty() => {
+            known_labels.join(", ")
+        }
+        None => rows_vec
+            .iter()
+            .filter_map(|row| row.label.clone())
+            .find(|label| label != "unknown")
+            .unwrap_or(String::from("unknown")),
+    };
+    let summary = rows_vec
+        .iter()
+        .flat_map(|row| row.content.split_whitespace())
+        .take(SUMMARY_WORDS)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let new_name = &match &options.title {
+        Some(title) if !title.trim().is_empty() => unused_name(conn, title.trim())?,
+        _ if options.summarize && !summary.is_empty() => unused_name(conn, &summary)?,
+        _ => rows_vec
+            .iter()
+            .map(|row| row.name.clone())
+            .collect::<Vec<_>>()
+            .join(","),
+    };
+    let created_at = rows_vec
+        .iter()
+        .filter_map(|row| row.created_at.clone())
+        .min();
+    conn.execute(
+        "INSERT INTO memos \
+         (name, content, label, updated_at, merged_from, created_at, content_hash) \
+         VALUES (?1, ?2, ?3, datetime('now'), ?4, ?5, ?6)",
+        (
+            new_name,
+            new_content,
+            new_label,
+            merged_from,
+            created_at,
+            db::content_hash(new_content),
+        ),
+    )
+    .map_err(sqlite_error)?;
+    // The contents of the merged memos become the history of the new one.
+    for part in &parts {
+        conn.execute(
+            "INSERT INTO memo_history (name, content, merged_from, merged_label) \
+             VALUES (?1, ?2, ?3, ?4)",
+            (new_name, &part.content, &part.name, &part.label),
+        )
+        .map_err(sqlite_error)?;
+    }
+
+    select_row(conn, new_name)
+}
+
+/// A memo that another memo was merged from, as recorded in its history.
+struct MergedPart {
+    name: String,
+    content: String,
+    /// `None` for memos merged before labels were recorded.
+    label: Option<String>,
+}
+
+/// Returns the memos that the memo was merged from, as recorded in its history by
+/// [`merge_rows`], in order.
+fn merged_parts(conn: &Connection, name: &str) -> Result<Vec<MergedPart>, Error> {
+    let mut select_stmt = conn
+        .prepare(
+            "SELECT merged_from, content, merged_label FROM memo_history \
+             WHERE name = ?1 AND merged_from IS NOT NULL ORDER BY id",
+        )
+        .map_err(sqlite_error)?;
+    let parts = select_stmt
+        .query_map([name], |row| {
+            Ok(MergedPart {
+                name: row.get(0)?,
+                content: row.get(1)?,
+                label: row.get(2)?,
+            })
+        })
+        .map_err(sqlite_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(sqlite_error)?;
+    Ok(parts)
+}
+
+/// Removes lines that occurred in earlier contents (or earlier in the same content),
+/// keeping the first occurrence. Blank lines are kept, and contents that end up empty
+/// are dropped.
+fn dedupe_lines(contents: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    contents
+        .iter()
+        .map(|content| {
+            content
+                .lines()
+                .filter(|line| {
+                    line.trim().is_empty() || seen.insert(line.trim().to_string())
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+                .trim()
+                .to_string()
+        })
+        .filter(|content| !content.is_empty())
+        .collect()
+}
+
+/// Updates row content
+///
+/// With `upsert`, a memo that doesn't exist yet is created with the "unknown" label.
+#[tauri::command]
+fn set_content(
+    name: &str,
+    new_content: &str,
+    upsert: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<(), Error> {
+    state.run_command("set_content", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let hash = db::content_hash(new_content);
+        // Not `ON CONFLICT(name)`, which needs the unique index that databases with
+        // duplicate names don't have.
+        retry_busy(|| {
+            let updated = conn
+                .execute(
+                    "UPDATE memos SET content = ?1, updated_at = datetime('now'), \
+                     content_hash = ?3 WHERE name = ?2",
+                    [new_content, name, &hash],
+                )
+                .map_err(sqlite_error)?;
+            if updated == 0 && upsert.unwrap_or(false) {
+                conn.execute(
+                    "INSERT INTO memos (content, name, label, updated_at, content_hash) \
+                     VALUES (?1, ?2, 'unknown', datetime('now'), ?3)",
+                    [new_content, name, &hash],
+                )
+                .map_err(sqlite_error)?;
+            }
+            Ok(())
+        })
+    })
+}
+
+/// Opens the memo's content in the configured editor (`MEMOS_EDITOR` or `EDITOR`) and
+/// saves it once the editor exits. Returns whether the content changed; it doesn't if the
+/// editor exits with an error, e.g. after `:cq` in Vim. Runs off the main thread, since
+/// it waits for the editor.
+#[tauri::command(async)]
+fn edit_transcript_external(
+    name: String,
+    state: tauri::State<'_, State>,
+) -> Result<bool, Error> {
+    state.run_command("edit_transcript_external", || {
+        state.check_writable()?;
+        let editor = state
+            .config
+            .read()
+            .map_err(tauri_error)?
+            .editor
+            .clone()
+            .ok_or_else(|| {
+                tauri_error("No editor configured, set MEMOS_EDITOR or EDITOR")
+            })?;
+        let old_content = {
+            let db_conn = state.db_conn.clone();
+            let guard = db_conn.lock().map_err(tauri_error)?;
+            select_row(&guard, &name)?.content
+        };
+        let dir = env::temp_dir().join("memos-edit");
+        fs::create_dir_all(&dir).map_err(tauri_error)?;
+        let stem = Path::new(&name).file_stem().unwrap_or_default();
+        let (path, mut file) = create_unique_file(&dir, &stem.to_string_lossy(), "txt")
+            .map_err(tauri_error)?;
+        let written = std::io::Write::write_all(&mut file, old_content.as_bytes());
+        drop(file);
+        if let Err(err) = written {
+            let _ = fs::remove_file(&path);
+            return Err(tauri_error(err));
+        }
+        let mut args = editor.split_whitespace();
+        let program = args.next().unwrap_or_default();
+        let status = Command::new(program).args(args).arg(&path).status();
+        let new_content = fs::read_to_string(&path);
+        let _ = fs::remove_file(&path);
+        let status = status.map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => {
+                tauri_error(format!("Editor {program} not found"))
+            }
+            _ => tauri_error(format!("Couldn't run {program}: {err}")),
+        })?;
+        let new_content = new_content.map_err(tauri_error)?;
+        if !status.success() || new_content == old_content {
+            return Ok(false);
+        }
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let hash = db::content_hash(&new_content);
+        // Only saved if nothing else changed the content while the editor was open.
+        let changed = retry_busy(|| {
+            guard
+                .execute(
+                    "UPDATE memos SET content = ?1, updated_at = datetime('now'), \
+                 content_hash = ?2 WHERE name = ?3 AND content = ?4",
+                    [&new_content, &hash, &name, &old_content],
+                )
+                .map_err(sqlite_error)
+        })?;
+        if changed == 0 {
+            return Err(tauri_error(format!(
+                "{name} was changed or removed while it was being edited"
+            )));
+        }
+        Ok(true)
+    })
+}
+
+/// Creates `{stem}.{extension}` in `dir`, or `{stem}-2.{extension}` and so on if it
+/// exists already, so that two edits of memos with the same stem don't share a file.
+fn create_unique_file(
+    dir: &Path,
+    stem: &str,
+    extension: &str,
+) -> std::io::Result<(PathBuf, fs::File)> {
+    for n in 1.. {
+        let path = match n {
+            1 => dir.join(format!("{stem}.{extension}")),
+            _ => dir.join(format!("{stem}-{n}.{extension}")),
+        };
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+/// Empties row content, e.g. before re-transcribing. Returns whether the row exists.
+#[tauri::command]
+fn clear_content(name: &str, state: tauri::State<State>) -> Result<bool, Error> {
+    state.run_command("clear_content", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let changed = conn
+            .execute(
+                "UPDATE memos SET content = '', updated_at = datetime('now'), \
+                 content_hash = ?2 WHERE name = ?1",
+                [name, &db::content_hash("")],
+            )
+            .map_err(tauri_error)?;
+        Ok(changed > 0)
+    })
+}
+
+/// How [`open`] plays memos. Every field is optional, e.g. `{ loopPlayback: true }`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct OpenOptions {
+    /// Skip and report missing files instead of failing the command.
+    best_effort: bool,
+    /// Play with something other than VLC.
+    player: Player,
+    /// Play a single file on repeat.
+    loop_playback: bool,
+    /// Play a single file from the position saved with [`set_playback_position`].
+    resume: bool,
+    /// Give each file its own player process instead of queueing them.
+    separate_windows: bool,
+    /// Play a single file from one of its [`list_bookmarks`].
+    bookmark_index: Option<usize>,
+}
+
+/// Play audio files in the stored files directory, using VLC (or another player), and
+/// exit afterwards.
+///
+/// If there are several files, it will play all of them one after the other. See
+/// [`OpenOptions`] for the rest.
+#[tauri::command]
+fn open(
+    names: Vec<&str>,
+    options: Option<OpenOptions>,
+    state: tauri::State<State>,
+) -> Result<BatchResult, Error> {
+    state.run_command("open", || {
+        let OpenOptions {
+            best_effort,
+            player,
+            loop_playback,
+            resume,
+            separate_windows,
+            bookmark_index,
+        } = options.unwrap_or_default();
+        if player.is_macos_only() && !cfg!(target_os = "macos") {
+            return Err(tauri_error("This command is only available on macOS"));
+        }
+        if names.len() > 1 {
+            if loop_playback || resume {
+                return Err(tauri_error(
+                    "Looping or resuming playback only works with a single memo",
+                ));
+            }
+            if bookmark_index.is_some() {
+                return Err(tauri_error(
+                    "Starting at a bookmark only works with a single memo",
+                ));
+            }
+        }
+        if resume && bookmark_index.is_some() {
+            return Err(tauri_error("Can't both resume and start at a bookmark"));
+        }
+        let start_secs = match (names.first(), bookmark_index) {
+            (Some(name), Some(index)) => {
+                let db_conn = state.db_conn.clone();
+                let guard = db_conn.lock().map_err(tauri_error)?;
+                let bookmark = query_bookmarks(&guard, name)?
+                    .into_iter()
+                    .nth(index)
+                    .ok_or_else(|| {
+                        tauri_error(format!("Memo {} has no bookmark #{}", name, index))
+                    })?;
+                Some(bookmark.secs)
+            }
+            (Some(name), None) if resume => {
+                let db_conn = state.db_conn.clone();
+                let guard = db_conn.lock().map_err(tauri_error)?;
+                guard
+                    .query_row(
+                        "SELECT playback_position_secs FROM memos WHERE name = ?1",
+                        [name],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(tauri_error)?
+                    .flatten()
+            }
+            _ => None,
+        };
+        let options = PlayOptions {
+            loop_playback,
+            start_secs,
+        };
+        player.check_options(&options).map_err(tauri_error)?;
+
+        let dir = state.storage_dir()?;
+
+        // Detect if any of the files don't exist, and throw an error if so.
+        let mut result = BatchResult::default();
+        let mut paths = Vec::new();
+        for file in names {
+            let path = {
+                let guard = state.db_conn.lock().map_err(tauri_error)?;
+                memo_path(&guard, &dir, file)?
+            };
+            if path.exists() {
+                result.succeeded.push(file.to_string());
+                paths.push(path);
+            } else {
+                let reason = format!("File {} doesn't exist", path.display());
+                if !best_effort {
+                    return Err(tauri_error(reason));
+                }
+                result.failed.push(Failure {
+                    name: file.to_string(),
+                    reason,
+                });
+            }
+        }
+        if paths.is_empty() {
+            return Ok(result);
+        }
+
+        let vlc_path = state.vlc_path()?;
+        let playlists: Vec<&[PathBuf]> = if separate_windows {
+            paths.chunks(1).collect()
+        } else {
+            vec![&paths]
+        };
+        for files in playlists {
+            let child = player
+                .command(&vlc_path, &dir, files, &options)
+                .spawn()
+                .map_err(tauri_error)?;
+            state.track_player(child)?;
+        }
+        Ok(result)
+    })
+}
+
+/// Add to Things (Inbox).
+///
+/// `mode` controls how the memos are mapped into Things, see [`ThingsMode`], and `label_as`
+/// how their labels are represented, see [`LabelDestination`]. Memos that
+/// were already pushed are skipped unless `skip_already_pushed` is `false`. Names that
+/// aren't in the database fail the command, or are reported with `best_effort`. Large
+/// pushes are split into several URLs, with a `things-progress` event after each.
+/// `content_as` turns spoken lists into checklists, see [`ContentMapping`].
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn add_to_things(
+    names: Vec<&str>,
+    mode: Option<ThingsMode>,
+    label_as: Option<LabelDestination>,
+    content_as: Option<ContentMapping>,
+    skip_already_pushed: Option<bool>,
+    best_effort: Option<bool>,
+    window: tauri::Window,
+    state: tauri::State<State>,
+) -> Result<PushResult, Error> {
+    state.run_command("add_to_things", || {
+        state.check_writable()?;
+        check_things_installed()?;
+
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let rows_vec = select_rows(conn, &names, state.rarray)?;
+        let mut failed = Vec::new();
+        for name in &names {
+            if !rows_vec.iter().any(|row| row.name == *name) {
+                let reason = format!("Memo {} doesn't exist", name);
+                if !best_effort.unwrap_or(false) {
+                    return Err(tauri_error(reason));
+                }
+                failed.push(Failure {
+                    name: name.to_string(),
+                    reason,
+                });
+            }
+        }
+        let (skipped, to_push): (Vec<Row>, Vec<Row>) =
+            rows_vec.into_iter().partition(|row| {
+                skip_already_pushed.unwrap_or(true) && row.pushed_to_things.is_some()
+            });
+        let result = PushResult {
+            pushed: to_push.iter().map(|row| row.name.clone()).collect(),
+            skipped: skipped.into_iter().map(|row| row.name).collect(),
+            failed,
+        };
+        if to_push.is_empty() {
+            return Ok(result);
+        }
+
+        let options = ThingsOptions {
+            mode: mode.unwrap_or_default(),
+            label_as: label_as.unwrap_or_default(),
+            content_as: content_as.unwrap_or_default(),
+        };
+        push_to_things(conn, &to_push, options, state.rarray, Some(&window))?;
+        Ok(result)
+    })
+}
+
+/// Fails if Things isn't available.
+fn check_things_installed() -> Result<(), Error> {
+    if !Path::new("/Applications/Things3.app").exists() {
+        return Err(tauri_error("Things is not installed"));
+    }
+    Ok(())
+}
+
+/// Longest `things:///json` URL to open at once. Longer pushes are split into several
+/// URLs, see [`things_chunks`].
+const THINGS_URL_LIMIT: usize = 30_000;
+
+/// Opens the Things URLs for the rows and marks them as pushed. With a `window`, emits
+/// `things-progress` after every URL.
+fn push_to_things(
+    conn: &Connection,
+    rows: &[Row],
+    options: ThingsOptions,
+    rarray: bool,
+    window: Option<&tauri::Window>,
+) -> Result<(), Error> {
+    // Add to Things, using things:///json. For now we won't remove the memos from the database - it seems too risky.
+    // TODO: I can use x-success to check that the things were added, and then it would be fine to remove them from the DB.
+    let mut progress = ThingsProgress {
+        pushed: 0,
+        total: rows.len(),
+    };
+    for chunk in things_chunks(rows, options)? {
+        let url = build_things_url(chunk, options)?;
+        Command::new("open").arg(url).spawn().map_err(tauri_error)?;
+
+        let names: Vec<&str> = chunk.iter().map(|row| row.name.as_str()).collect();
+        set_pushed(conn, &names, rarray)?;
+
+        progress.pushed += chunk.len();
+        if let Some(window) = window {
+            if let Err(err) = window.emit("things-progress", progress.clone()) {
+                eprintln!("Couldn't emit Things progress: {err}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets `pushed_to_things` of the memos to now, and returns how many there are.
+fn set_pushed(conn: &Connection, names: &[&str], rarray: bool) -> Result<usize, Error> {
+    let (filter, params) = names_filter("name", names, rarray);
+    conn.execute(
+        &format!(
+            "UPDATE memos SET pushed_to_things = datetime('now') WHERE {}",
+            filter
+        ),
+        rusqlite::params_from_iter(params),
+    )
+    .map_err(tauri_error)
+}
+
+/// Splits rows into runs whose Things URLs fit into [`THINGS_URL_LIMIT`], so that Things
+/// doesn't get one giant URL. Only separate to-dos can be split; a memo that doesn't fit
+/// on its own still gets its own URL.
+fn things_chunks(rows: &[Row], options: ThingsOptions) -> Result<Vec<&[Row]>, Error> {
+    if !matches!(options.mode, ThingsMode::SeparateTodos) {
+        return Ok(vec![rows]);
+    }
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for end in 2..=rows.len() {
+        if end - start > 1
+            && build_things_url(&rows[start..end], options)?.len() > THINGS_URL_LIMIT
+        {
+            chunks.push(&rows[start..end - 1]);
+            start = end - 1;
+        }
+    }
+    if start < rows.len() {
+        chunks.push(&rows[start..]);
+    }
+    Ok(chunks)
+}
+
+/// Builds the `things:///json` URL that `add_to_things` would open, without opening it.
+#[tauri::command]
+fn things_url(
+    names: Vec<&str>,
+    mode: Option<ThingsMode>,
+    label_as: Option<LabelDestination>,
+    content_as: Option<ContentMapping>,
+    state: tauri::State<State>,
+) -> Result<String, Error> {
+    state.run_command("things_url", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let rows_vec = select_rows(&guard, &names, state.rarray)?;
+        let options = ThingsOptions {
+            mode: mode.unwrap_or_default(),
+            label_as: label_as.unwrap_or_default(),
+            content_as: content_as.unwrap_or_default(),
+        };
+        build_things_url(&rows_vec, options)
+    })
+}
+
+/// Builds the `things:///json` URL for adding memos to Things.
+fn build_things_url(rows: &[Row], options: ThingsOptions) -> Result<String, Error> {
+    let items = things_items(rows, options);
+    let url = things3::json_url(&items, true, None).map_err(tauri_error)?;
+    Ok(url.into())
+}
+
+/// Converts memos into Things items according to the chosen options.
+fn things_items(rows: &[Row], options: ThingsOptions) -> Vec<things3::Item> {
+    let todo = |row: &Row| {
+        let (notes, tags) = label_fields(row.known_label().into_iter(), options.label_as);
+        let parts: Vec<&str> = match options.content_as {
+            ContentMapping::Title => Vec::new(),
+            ContentMapping::ChecklistLines => row.content.lines().collect(),
+            ContentMapping::ChecklistSentences => sentences(&row.content),
+        };
+        let checklist_items: Vec<things3::ChecklistItem> = parts
+            .into_iter()
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| things3::ChecklistItem {
+                title: part.to_string(),
+            })
+            .collect();
+        let title = match options.content_as {
+            ContentMapping::Title => row.content.clone(),
+            _ => format!("List ({} items)", checklist_items.len()),
+        };
+        things3::Item::Todo(things3::Todo {
+            title,
+            notes,
+            tags,
+            checklist_items,
+        })
+    };
+    let title = format!("Memos ({})", rows.len());
+    match options.mode {
+        ThingsMode::SeparateTodos => rows.iter().map(todo).collect(),
+        ThingsMode::SingleTodoWithChecklist => {
+            let mut labels: Vec<&str> =
+                rows.iter().filter_map(Row::known_label).collect();
+            labels.sort();
+            labels.dedup();
+            let (notes, tags) = label_fields(labels.into_iter(), options.label_as);
+            vec![things3::Item::Todo(things3::Todo {
+                title,
+                notes,
+                tags,
+                checklist_items: rows
+                    .iter()
+                    .map(|row| things3::ChecklistItem {
+                        title: row.content.clone(),
+                    })
+                    .collect(),
+            })]
+        }
+        ThingsMode::Project => vec![things3::Item::Project(things3::Project {
+            title,
+            notes: None,
+            items: rows.iter().map(todo).collect(),
+        })],
+    }
+}
+
+/// Splits text after `.`, `!` and `?` that are followed by whitespace.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_space = chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+        if matches!(c, '.' | '!' | '?') && at_space {
+            sentences.push(&text[start..i + c.len_utf8()]);
+            start = i + c.len_utf8();
+        }
+    }
+    sentences.push(&text[start..]);
+    sentences
+}
+
+/// Lists audio files in the storage directory that have no matching memo
+#[tauri::command]
+fn orphaned_files(state: tauri::State<State>) -> Result<Vec<String>, Error> {
+    state.run_command("orphaned_files", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let orphans = find_orphaned_files(&guard, &state.storage_dir()?)?;
+        Ok(orphans
+            .iter()
+            .map(|name| storage::display_name(name))
+            .collect())
+    })
+}
+
+/// Imports or deletes audio files that have no matching memo, returns the affected files
+///
+/// Files with names that aren't valid UTF-8 can't be imported, since memo names are text.
+#[tauri::command]
+fn repair_orphaned_files(
+    action: OrphanAction,
+    state: tauri::State<State>,
+) -> Result<Vec<String>, Error> {
+    state.run_command("repair_orphaned_files", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let dir = state.storage_dir()?;
+        let orphans = find_orphaned_files(conn, &dir)?;
+        let mut affected = Vec::new();
+        match action {
+            OrphanAction::Import => {
+                let mut insert_stmt = conn
+                    .prepare(
+                        "INSERT INTO memos (name, content, label, content_hash) \
+                         VALUES (?1, '', 'unknown', ?2)",
+                    )
+                    .map_err(tauri_error)?;
+                let hash = db::content_hash("");
+                for name in orphans.iter().filter_map(|name| name.to_str()) {
+                    insert_stmt.execute([name, &hash]).map_err(tauri_error)?;
+                    affected.push(name.to_string());
+                }
+            }
+            OrphanAction::Delete => {
+                for name in &orphans {
+                    fs::remove_file(storage::resolve(&dir, name)).map_err(tauri_error)?;
+                    affected.push(storage::display_name(name));
+                }
+            }
+        }
+        Ok(affected)
+    })
+}
+
+/// Returns names of the files in the storage directory that aren't in the database,
+/// except for transcripts (see [`load_sidecar`]). The files of merged memos are in the
+/// database, see [`storage::memo_files`].
+fn find_orphaned_files(conn: &Connection, dir: &Path) -> Result<Vec<OsString>, Error> {
+    let files = storage::file_names(dir).map_err(tauri_error)?;
+    let mut select_stmt = conn
+        .prepare("SELECT name, merged_from FROM memos")
+        .map_err(tauri_error)?;
+    let memos = select_stmt
+        .query_map((), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })
+        .map_err(tauri_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(tauri_error)?;
+    let known: HashSet<&str> = memos
+        .iter()
+        .flat_map(|(name, merged_from)| storage::memo_files(name, merged_from.as_deref()))
+        .collect();
+    Ok(files
+        .into_iter()
+        .filter(|file| !storage::is_sidecar(file))
+        .filter(|file| file.to_str().map_or(true, |file| !known.contains(file)))
+        .collect())
+}
+
+/// Counts memos, characters and words across all memos
+#[tauri::command]
+fn content_stats(state: tauri::State<State>) -> Result<ContentStats, Error> {
+    state.run_command("content_stats", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let mut select_stmt = conn
+            .prepare("SELECT content FROM memos")
+            .map_err(tauri_error)?;
+        let mut rows = select_stmt.query(()).map_err(tauri_error)?;
+        let (mut memos, mut characters, mut words) = (0, 0, 0);
+        while let Some(row) = rows.next().map_err(tauri_error)? {
+            let content = row
+                .get_ref(0)
+                .map_err(tauri_error)?
+                .as_str()
+                .unwrap_or_default();
+            memos += 1;
+            characters += content.chars().count();
+            words += content.split_whitespace().count();
+        }
+        Ok(ContentStats {
+            memos,
+            characters,
+            words,
+            average_words: if memos == 0 {
+                0.0
+            } else {
+                words as f64 / memos as f64
+            },
+        })
+    })
+}
+
+/// Plays all memos with the given label one after the other, in name order or with
+/// `shuffle` in random order, and returns their names in that order. Merged memos play
+/// the files they were merged from.
+#[tauri::command]
+fn play_label(
+    label: &str,
+    shuffle: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<Vec<String>, Error> {
+    state.run_command("play_label", || {
+        let order = if shuffle.unwrap_or(false) {
+            "RANDOM()"
+        } else {
+            "name ASC"
+        };
+        // The connection is released before `open`, which looks up where the files are.
+        let memos = {
+            let db_conn = state.db_conn.clone();
+            let guard = db_conn.lock().map_err(tauri_error)?;
+            let mut select_stmt = guard
+                .prepare(&format!(
+                    "SELECT name, merged_from FROM memos WHERE label = ?1 ORDER BY {}",
+                    order
+                ))
+                .map_err(tauri_error)?;
+            let memos = select_stmt
+                .query_map([label], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+                })
+                .map_err(tauri_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(tauri_error)?;
+            memos
+        };
+        if memos.is_empty() {
+            return Err(tauri_error(format!("No memos with label {}", label)));
+        }
+        let files = memos
+            .iter()
+            .flat_map(|(name, merged_from)| {
+                storage::memo_files(name, merged_from.as_deref())
+            })
+            .collect();
+        open(files, None, state.clone())?;
+        Ok(memos.into_iter().map(|(name, _)| name).collect())
+    })
+}
+
+/// Stars or unstars a memo. Returns whether the row exists.
+#[tauri::command]
+fn set_favorite(
+    name: &str,
+    favorite: bool,
+    state: tauri::State<State>,
+) -> Result<bool, Error> {
+    state.run_command("set_favorite", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let changed = conn
+            .execute(
+                "UPDATE memos SET favorite = ?1 WHERE name = ?2",
+                (favorite, name),
+            )
+            .map_err(tauri_error)?;
+        Ok(changed > 0)
+    })
+}
+
+/// Saves where to resume playing a memo with `open`, or forgets it with `None`. Returns
+/// whether the row exists.
+#[tauri::command]
+fn set_playback_position(
+    name: &str,
+    secs: Option<f64>,
+    state: tauri::State<State>,
+) -> Result<bool, Error> {
+    state.run_command("set_playback_position", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let changed = guard
+            .execute(
+                "UPDATE memos SET playback_position_secs = ?1 WHERE name = ?2",
+                (secs, name),
+            )
+            .map_err(tauri_error)?;
+        Ok(changed > 0)
+    })
+}
+
+/// Loads starred memos
+#[tauri::command]
+fn load_favorites(state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("load_favorites", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        query_rows(&guard, "favorite ORDER BY name ASC", ())
+    })
+}
+
+/// Lists columns of the memos table with their declared types
+#[tauri::command]
+fn schema(state: tauri::State<State>) -> Result<Vec<(String, String)>, Error> {
+    state.run_command("schema", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        db::columns(&guard).map_err(tauri_error)
+    })
+}
+
+/// Imports rows in one transaction, resolving name collisions according to `policy`
+#[tauri::command]
+fn import(
+    rows: Vec<Row>,
+    policy: ConflictPolicy,
+    state: tauri::State<State>,
+) -> Result<ImportSummary, Error> {
+    state.run_command("import", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(tauri_error)?;
+        let summary = import_rows(&tx, &rows, policy)?;
+        tx.commit().map_err(tauri_error)?;
+        Ok(summary)
+    })
+}
+
+/// Inserts rows, resolving name collisions according to `policy`.
+fn import_rows(
+    conn: &Connection,
+    rows: &[Row],
+    policy: ConflictPolicy,
+) -> Result<ImportSummary, Error> {
+    let mut summary = ImportSummary::default();
+    for row in rows {
+        let name = if !memo_exists(conn, &row.name)? {
+            summary.inserted += 1;
+            row.name.clone()
+        } else {
+            match policy {
+                ConflictPolicy::Skip => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                ConflictPolicy::Overwrite => {
+                    conn.execute("DELETE FROM memos WHERE name = ?1", [&row.name])
+                        .map_err(tauri_error)?;
+                    summary.overwritten += 1;
+                    row.name.clone()
+                }
+                ConflictPolicy::Rename => {
+                    summary.renamed += 1;
+                    free_name(conn, &row.name)?
+                }
+            }
+        };
+        conn.execute(
+            "INSERT INTO memos \
+             (name, content, label, pushed_to_things, updated_at, favorite, merged_from, \
+             created_at, content_hash, moved_to) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            (
+                &name,
+                &row.content,
+                &row.label,
+                &row.pushed_to_things,
+                &row.updated_at,
+                row.favorite,
+                &row.merged_from,
+                &row.created_at,
+                db::content_hash(&row.content),
+                &row.moved_to,
+            ),
+        )
+        .map_err(tauri_error)?;
+    }
+    Ok(summary)
+}
+
+/// Whether a memo with the given name exists
+fn memo_exists(conn: &Connection, name: &str) -> Result<bool, Error> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM memos WHERE name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+    .map_err(tauri_error)
+}
+
+/// Returns the path of the audio file for a memo, see [`storage::locate`]. Names that
+/// aren't memos, like the files of merged memos, are looked up in the storage directory.
+fn memo_path(conn: &Connection, dir: &Path, name: &str) -> Result<PathBuf, Error> {
+    let moved_to: Option<String> = conn
+        .query_row(
+            "SELECT moved_to FROM memos WHERE name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(tauri_error)?
+        .flatten();
+    Ok(storage::locate(dir, name, moved_to.as_deref()))
+}
+
+/// Returns `name` if no memo has it yet, and a [`free_name`] otherwise.
+fn unused_name(conn: &Connection, name: &str) -> Result<String, Error> {
+    if memo_exists(conn, name)? {
+        free_name(conn, name)
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+/// Finds an unused name like `memo (2).m4a` for a memo called `memo.m4a`.
+fn free_name(conn: &Connection, name: &str) -> Result<String, Error> {
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map_or(name.into(), |stem| stem.to_string_lossy());
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    for n in 2.. {
+        let candidate = format!("{} ({}){}", stem, n, extension);
+        if !memo_exists(conn, &candidate)? {
+            return Ok(candidate);
+        }
+    }
+    unreachable!()
+}
+
+/// Starts playing a memo and adds it to Things
+#[tauri::command]
+fn play_and_push(
+    name: &str,
+    window: tauri::Window,
+    state: tauri::State<State>,
+) -> Result<(), Error> {
+    state.run_command("play_and_push", || {
+        open(vec![name], None, state.clone())
+            .map_err(|err| tauri_error(format!("Couldn't play {}: {}", name, err)))?;
+        add_to_things(
+            vec![name],
+            None,
+            None,
+            None,
+            Some(false),
+            None,
+            window,
+            state.clone(),
+        )
+        .map_err(|err| {
+            tauri_error(format!("Couldn't add {} to Things: {}", name, err))
+        })?;
+        Ok(())
+    })
+}
+
+/// Loads memos whose content length (in characters) is within the given bounds
+#[tauri::command]
+fn by_length(
+    min: Option<usize>,
+    max: Option<usize>,
+    state: tauri::State<State>,
+) -> Result<Vec<Row>, Error> {
+    state.run_command("by_length", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        query_rows(
+            &guard,
+            "(?1 IS NULL OR LENGTH(content) >= ?1) \
+             AND (?2 IS NULL OR LENGTH(content) <= ?2) ORDER BY name ASC",
+            (min, max),
+        )
+    })
+}
+
+/// Counts memos by the (uppercased) first letter of their name, for an A-Z index.
+/// Names that don't start with a letter are counted under '#'.
+#[tauri::command]
+fn name_index(state: tauri::State<State>) -> Result<Vec<(char, i64)>, Error> {
+    state.run_command("name_index", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let mut select_stmt = conn
+            .prepare(
+                "SELECT SUBSTR(name, 1, 1) AS initial, COUNT(*) FROM memos \
+                 GROUP BY initial",
+            )
+            .map_err(tauri_error)?;
+        let mut index = BTreeMap::new();
+        let mut rows = select_stmt.query(()).map_err(tauri_error)?;
+        while let Some(row) = rows.next().map_err(tauri_error)? {
+            let initial: Option<String> = row.get(0).map_err(tauri_error)?;
+            let count: i64 = row.get(1).map_err(tauri_error)?;
+            let bucket = match initial.and_then(|initial| initial.chars().next()) {
+                Some(c) if c.is_alphabetic() => c.to_uppercase().next().unwrap_or(c),
+                _ => '#',
+            };
+            *index.entry(bucket).or_insert(0) += count;
+        }
+        Ok(index.into_iter().collect())
+    })
+}
+
+/// Loads memos that contain all words from the query, in the name or content
+#[tauri::command]
+fn search(query: &str, state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("search", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        search_rows(&guard, query)
+    })
+}
+
+/// Finds rows matching a search query using the full-text index.
+fn search_rows(conn: &Connection, query: &str) -> Result<Vec<Row>, Error> {
+    // Quote every word, so that the query can't contain FTS5 syntax.
+    let fts_query = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if fts_query.is_empty() {
+        return Ok(Vec::new());
+    }
+    query_rows(
+        conn,
+        "rowid IN (SELECT rowid FROM memos_fts WHERE memos_fts MATCH ?1) \
+         ORDER BY name ASC",
+        [fts_query],
+    )
+}
+
+/// Rebuilds the full-text search index from scratch
+#[tauri::command]
+fn rebuild_search_index(state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("rebuild_search_index", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(tauri_error)?;
+        db::rebuild_search_index(&tx).map_err(tauri_error)?;
+        tx.commit().map_err(tauri_error)?;
+        Ok(())
+    })
+}
+
+/// Returns the notes and tags representing labels in a Things to-do.
+fn label_fields<'a>(
+    labels: impl Iterator<Item = &'a str>,
+    label_as: LabelDestination,
+) -> (Option<String>, Vec<String>) {
+    match label_as {
+        LabelDestination::Ignore => (None, Vec::new()),
+        LabelDestination::Tag => (None, labels.map(String::from).collect()),
+        LabelDestination::NotePrefix => {
+            let labels = labels.collect::<Vec<_>>().join(", ");
+            ((!labels.is_empty()).then_some(labels), Vec::new())
+        }
+    }
+}
+
+/// Writes the Things JSON payload for the memos to a file, for importing into Things
+/// without the URL length limit
+#[tauri::command]
+fn export_things_json(
+    names: Vec<&str>,
+    path: &str,
+    mode: Option<ThingsMode>,
+    label_as: Option<LabelDestination>,
+    content_as: Option<ContentMapping>,
+    state: tauri::State<State>,
+) -> Result<(), Error> {
+    state.run_command("export_things_json", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let rows_vec = select_rows(&guard, &names, state.rarray)?;
+        let options = ThingsOptions {
+            mode: mode.unwrap_or_default(),
+            label_as: label_as.unwrap_or_default(),
+            content_as: content_as.unwrap_or_default(),
+        };
+        let items = things_items(&rows_vec, options);
+        let json = serde_json::to_string_pretty(&items).map_err(tauri_error)?;
+        fs::write(path, json).map_err(tauri_error)?;
+        Ok(())
+    })
+}
+
+/// Writes the memos as TaskPaper tasks, which Things (and TaskPaper) can import: the
+/// first line of the content is the task and the other lines are its notes. Labels
+/// become tags, with characters other than letters, digits, `-` and `_` replaced by `_`.
+#[tauri::command]
+fn export_taskpaper(
+    names: Vec<&str>,
+    path: &str,
+    state: tauri::State<State>,
+) -> Result<(), Error> {
+    state.run_command("export_taskpaper", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let rows_vec = select_rows(&guard, &names, state.rarray)?;
+        let mut taskpaper = String::new();
+        for row in &rows_vec {
+            let mut lines = row
+                .content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty());
+            taskpaper.push_str("- ");
+            taskpaper.push_str(lines.next().unwrap_or(&row.name));
+            if let Some(label) = row.known_label() {
+                let tag: String = label
+                    .chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || c == '-' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                taskpaper.push_str(" @");
+                taskpaper.push_str(&tag);
+            }
+            taskpaper.push('\n');
+            for line in lines {
+                taskpaper.push('\t');
+                taskpaper.push_str(line);
+                taskpaper.push('\n');
+            }
+        }
+        fs::write(path, taskpaper).map_err(tauri_error)?;
+        Ok(())
+    })
+}
+
+/// Loads memos that don't have a label yet
+#[tauri::command]
+fn unlabeled(state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("unlabeled", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        query_rows(
+            &guard,
+            &format!("{} IS NULL ORDER BY name ASC", KNOWN_LABEL),
+            (),
+        )
+    })
+}
+
+/// Loads memos with any of the given labels. "unknown" and "" select the memos without
+/// a label, same as [`unlabeled`].
+#[tauri::command]
+fn load_by_labels(
+    labels: Vec<&str>,
+    state: tauri::State<State>,
+) -> Result<Vec<Row>, Error> {
+    state.run_command("load_by_labels", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let (unknown, known): (Vec<&str>, Vec<&str>) = labels
+            .into_iter()
+            .partition(|label| label.is_empty() || *label == "unknown");
+        let (mut filter, params) = names_filter(KNOWN_LABEL, &known, state.rarray);
+        if !unknown.is_empty() {
+            filter = format!("({} OR {} IS NULL)", filter, KNOWN_LABEL);
+        }
+        query_rows(
+            &guard,
+            &format!("{} ORDER BY name ASC", filter),
+            rusqlite::params_from_iter(params),
+        )
+    })
+}
+
+/// Loads labeled memos whose label is none of the given ones, i.e. the opposite of
+/// [`load_by_labels`] except that memos without a label are never included.
+#[tauri::command]
+fn not_in_labels(
+    labels: Vec<&str>,
+    state: tauri::State<State>,
+) -> Result<Vec<Row>, Error> {
+    state.run_command("not_in_labels", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let (filter, params) = names_filter(KNOWN_LABEL, &labels, state.rarray);
+        query_rows(
+            &guard,
+            &format!(
+                "{} IS NOT NULL AND NOT {} ORDER BY name ASC",
+                KNOWN_LABEL, filter
+            ),
+            rusqlite::params_from_iter(params),
+        )
+    })
+}
+
+/// Checks the database for corruption. Returns "ok" or the list of problems.
+#[tauri::command]
+fn check_integrity(
+    quick: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<String, Error> {
+    state.run_command("check_integrity", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        db::integrity_check(&guard, quick.unwrap_or(false)).map_err(tauri_error)
+    })
+}
+
+/// Loads memos created on the given day (`YYYY-MM-DD`) in local time
+#[tauri::command]
+fn on_date(date: &str, state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("on_date", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        // A modifier makes SQLite normalize dates like 2023-02-30, which then don't
+        // match.
+        let valid = conn
+            .query_row("SELECT date(?1, '+0 days') IS ?1", [date], |row| {
+                row.get::<_, bool>(0)
+            })
+            .map_err(tauri_error)?;
+        if !valid {
+            return Err(tauri_error(format!(
+                "Not a valid YYYY-MM-DD date: {}",
+                date
+            )));
+        }
+        // `created_at` is in UTC, so convert it before taking the day.
+        query_rows(
+            conn,
+            "date(created_at, 'localtime') = ?1 ORDER BY created_at ASC",
+            [date],
+        )
+    })
+}
+
+/// Plays a random memo that has a file, and returns it
+#[tauri::command]
+fn play_random(state: tauri::State<State>) -> Result<Row, Error> {
+    state.run_command("play_random", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let dir = state.storage_dir()?;
+        // Only names are loaded, and only until one with a file turns up.
+        let name = {
+            let mut select_stmt = guard
+                .prepare("SELECT name, moved_to FROM memos ORDER BY RANDOM()")
+                .map_err(tauri_error)?;
+            let mut names = select_stmt.query(()).map_err(tauri_error)?;
+            let mut found = None;
+            while let Some(row) = names.next().map_err(tauri_error)? {
+                let name: String = row.get(0).map_err(tauri_error)?;
+                let moved_to: Option<String> = row.get(1).map_err(tauri_error)?;
+                if storage::locate(&dir, &name, moved_to.as_deref()).is_file() {
+                    found = Some(name);
+                    break;
+                }
+            }
+            found.ok_or_else(|| tauri_error("None of the memos have a file to play"))?
+        };
+        let row = select_row(&guard, &name)?;
+        // `open` looks up where the file is, which needs the connection.
+        drop(guard);
+        open(vec![&row.name], None, state.clone())?;
+        Ok(row)
+    })
+}
+
+/// Appends the memo's content to the notes of an existing Things to-do, given its ID
+/// (Copy Link in Things). Needs the Things auth token in `THINGS_AUTH_TOKEN`.
+#[tauri::command]
+fn append_to_things(
+    name: &str,
+    id: &str,
+    state: tauri::State<State>,
+) -> Result<(), Error> {
+    state.run_command("append_to_things", || {
+        state.check_writable()?;
+        check_things_installed()?;
+        let auth_token = state
+            .config
+            .read()
+            .map_err(tauri_error)?
+            .things_auth_token
+            .clone()
+            .ok_or_else(|| tauri_error("THINGS_AUTH_TOKEN not set"))?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let conn = &*guard;
+        let row = select_row(conn, name)?;
+        let item = things3::Item::Update {
+            id: id.to_string(),
+            append_notes: format!("\n\n{}", row.content),
+        };
+        let url =
+            things3::json_url(&[item], false, Some(&auth_token)).map_err(tauri_error)?;
+        Command::new("open")
+            .arg(url.as_str())
+            .spawn()
+            .map_err(tauri_error)?;
+        conn.execute(
+            "UPDATE memos SET pushed_to_things = datetime('now') WHERE name = ?1",
+            [name],
+        )
+        .map_err(tauri_error)?;
+        Ok(())
+    })
+}
+
+/// Renders the memo's content as Markdown, sanitized so that it's safe to insert into
+/// the page
+#[tauri::command]
+fn content_html(name: &str, state: tauri::State<State>) -> Result<String, Error> {
+    state.run_command("content_html", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let row = select_row(&guard, name)?;
+        if row.content.trim().is_empty() {
+            return Ok(String::new());
+        }
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(
+            &mut html,
+            pulldown_cmark::Parser::new(&row.content),
+        );
+        Ok(ammonia::clean(&html))
+    })
+}
+
+/// Moves a memo's file out of `VOICE_MEMOS_STORAGE` into `dest_dir`, e.g. to archive it.
+/// The memo stays in the database, with the new path in `moved_to`. Returns the new
+/// path.
+#[tauri::command]
+fn move_file(
+    name: &str,
+    dest_dir: &str,
+    state: tauri::State<State>,
+) -> Result<String, Error> {
+    state.run_command("move_file", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        if !memo_exists(&guard, name)? {
+            return Err(tauri_error(format!("No such memo: {}", name)));
+        }
+        let dir = state.storage_dir()?;
+        let from = storage::resolve(&dir, name);
+        if !from.is_file() {
+            return Err(tauri_error(format!(
+                "File {} doesn't exist",
+                from.display()
+            )));
+        }
+        let dest_dir = Path::new(dest_dir);
+        let writable = fs::metadata(dest_dir)
+            .map(|metadata| metadata.is_dir() && !metadata.permissions().readonly())
+            .unwrap_or(false);
+        if !writable {
+            return Err(tauri_error(format!(
+                "{} is not a writable directory",
+                dest_dir.display()
+            )));
+        }
+        let to = storage::move_file(&from, dest_dir)
+            .map_err(|err| tauri_error(format!("Couldn't move {}: {}", name, err)))?;
+        let to = to.display().to_string();
+        let recorded = retry_busy(|| {
+            guard
+                .execute(
+                    "UPDATE memos SET moved_to = ?1 WHERE name = ?2",
+                    [&to, name],
+                )
+                .map_err(sqlite_error)
+        });
+        if let Err(err) = recorded {
+            // The file is put back, so that the memo doesn't lose track of it.
+            let _ = storage::move_file(Path::new(&to), &dir);
+            return Err(err);
+        }
+        Ok(to)
+    })
+}
+
+/// Probes the memo's file for its format, codec, sample rate, channels, bitrate and
+/// duration
+#[tauri::command]
+fn audio_info(name: &str, state: tauri::State<State>) -> Result<audio::AudioInfo, Error> {
+    state.run_command("audio_info", || {
+        let path = {
+            let guard = state.db_conn.lock().map_err(tauri_error)?;
+            memo_path(&guard, &state.storage_dir()?, name)?
+        };
+        if !path.is_file() {
+            return Err(Error::NotFound(path.display().to_string()));
+        }
+        audio::info(&state.ffprobe_path()?, &path).map_err(tauri_error)
+    })
+}
+
+/// Runs the memo's file through an FFmpeg filter into a hidden file next to it, then puts
+/// that in place of the original, which is kept in `originals`.
+fn filter_audio(state: &State, name: &str, filter: &str) -> Result<(), Error> {
+    state.check_writable()?;
+    let dir = state.storage_dir()?;
+    let path = storage::resolve(&dir, name);
+    if !path.is_file() {
+        return Err(Error::NotFound(path.display().to_string()));
+    }
+    let temp = dir.join(format!(".filtered-{name}"));
+    let result = audio::filter(&state.ffmpeg_path()?, &path, &temp, filter)
+        .map_err(Error::Message)
+        .and_then(|()| {
+            storage::replace_keeping_original(&dir, name, &temp).map_err(tauri_error)
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.map(|_| ())
+}
+
+/// Normalizes the loudness of the memo's audio (EBU R128), keeping the original in
+/// `originals`. Runs off the main thread, since re-encoding takes a while.
+#[tauri::command(async)]
+fn normalize_audio(name: String, state: tauri::State<'_, State>) -> Result<(), Error> {
+    state.run_command("normalize_audio", || {
+        filter_audio(&state, &name, "loudnorm=I=-16:TP=-1.5:LRA=11")
+    })
+}
+
+/// Cuts leading and trailing silence from the memo's audio, keeping the original in
+/// `originals`, and returns the new duration. Silence is anything quieter than
+/// `threshold_db` (-50 dB by default), and `keep_silence_secs` of it (0.2 s by default)
+/// is left at each end so that the speech doesn't start abruptly. The saved playback
+/// position and the bookmarks are removed, since they no longer fit.
+#[tauri::command(async)]
+fn trim_silence(
+    name: String,
+    threshold_db: Option<f64>,
+    keep_silence_secs: Option<f64>,
+    state: tauri::State<'_, State>,
+) -> Result<Option<f64>, Error> {
+    state.run_command("trim_silence", || {
+        // `start_duration=0` stops trimming at the first sound, however short.
+        let trim_start = format!(
+            "silenceremove=start_periods=1:start_duration=0:start_threshold={}dB:\
+             start_silence={}",
+            threshold_db.unwrap_or(-50.0),
+            keep_silence_secs.unwrap_or(0.2),
+        );
+        // silenceremove only trims the start well, so the end is trimmed in reverse.
+        let filter = format!("{trim_start},areverse,{trim_start},areverse");
+        filter_audio(&state, &name, &filter)?;
+        {
+            let db_conn = state.db_conn.clone();
+            let mut guard = db_conn.lock().map_err(tauri_error)?;
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            tx.execute(
+                "UPDATE memos SET playback_position_secs = NULL WHERE name = ?1",
+                [&name],
+            )
+            .map_err(sqlite_error)?;
+            tx.execute("DELETE FROM bookmarks WHERE name = ?1", [&name])
+                .map_err(sqlite_error)?;
+            tx.commit().map_err(sqlite_error)?;
+        }
+        let path = storage::resolve(&state.storage_dir()?, &name);
+        let info = audio::info(&state.ffprobe_path()?, &path).map_err(tauri_error)?;
+        Ok(info.duration_secs)
+    })
+}
+
+/// Counts memos with and without audio files, and the total size of the files. Merged
+/// memos count as having files if all the files they were merged from exist.
+#[tauri::command]
+fn storage_summary(state: tauri::State<State>) -> Result<StorageSummary, Error> {
+    state.run_command("storage_summary", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let dir = state.storage_dir()?;
+        let mut summary = StorageSummary::default();
+        for row in query_rows(&guard, "1", ())? {
+            summary.memos += 1;
+            let mut missing = false;
+            for file in row.files(&dir) {
+                match fs::metadata(file) {
+                    Ok(metadata) if metadata.is_file() => summary.bytes += metadata.len(),
+                    _ => missing = true,
+                }
+            }
+            if missing {
+                summary.missing_files += 1;
+            } else {
+                summary.with_files += 1;
+            }
+        }
+        Ok(summary)
+    })
+}
+
+/// Lists merged memos whose label isn't the label of any memo they were merged from, or
+/// all of those labels joined as `merge` does. The original labels are recorded when
+/// merging, see [`merged_parts`]; for memos merged before that, they are taken from the
+/// `## label` headings that `merge` adds with `label_sections`, if there are any.
+#[tauri::command]
+fn audit_labels(state: tauri::State<State>) -> Result<Vec<String>, Error> {
+    state.run_command("audit_labels", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let rows_vec =
+            query_rows(&guard, "merged_from IS NOT NULL ORDER BY name ASC", ())?;
+        let mut suspicious = vec![];
+        for row in rows_vec {
+            let parts = merged_parts(&guard, &row.name)?;
+            let mut source_labels: Vec<&str> = parts
+                .iter()
+                .filter_map(|part| part.label.as_deref())
+                .filter(|label| is_known_label(label))
+                .collect();
+            if parts.iter().all(|part| part.label.is_none()) {
+                source_labels = row
+                    .content
+                    .split("\n\n")
+                    .filter_map(|part| part.strip_prefix("## "))
+                    .filter_map(|part| part.lines().next())
+                    .collect();
+            }
+            if source_labels.is_empty() {
+                continue;
+            }
+            let mut seen = HashSet::new();
+            let distinct: Vec<&str> = source_labels
+                .iter()
+                .copied()
+                .filter(|label| seen.insert(*label))
+                .collect();
+            let matches = row.known_label().is_some_and(|label| {
+                distinct.contains(&label) || label == distinct.join(", ")
+            });
+            if !matches {
+                suspicious.push(row.name);
+            }
+        }
+        Ok(suspicious)
+    })
+}
+
+/// Splits a merged memo back into the memos it was merged from, using `merged_from` or
+/// the comma-joined name, and returns them. The memos get the contents they had when
+/// they were merged, see [`merged_parts`]. If the merged memo was edited since (or was
+/// merged before the contents were recorded), its content is split at blank lines
+/// instead, which fails if the number of parts doesn't match the number of memos. All
+/// memos get the label of the merged one.
+#[tauri::command]
+fn unmerge(merged_name: &str, state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("unmerge", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let merged = select_row(&tx, merged_name)?;
+        let sources = merged.merged_from.as_deref().unwrap_or(&merged.name);
+        let names: Vec<&str> = sources.split(',').collect();
+        let parts = merged_parts(&tx, merged_name)?;
+        let edited: bool = tx
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM memo_history \
+                 WHERE name = ?1 AND merged_from IS NULL)",
+                [merged_name],
+                |row| row.get(0),
+            )
+            .map_err(sqlite_error)?;
+        let contents: Vec<&str> = if !edited
+            && parts
+                .iter()
+                .map(|part| part.name.as_str())
+                .eq(names.iter().copied())
+        {
+            parts.iter().map(|part| part.content.as_str()).collect()
+        } else {
+            merged.content.split("\n\n").collect()
+        };
+        if names.len() < 2 {
+            return Err(tauri_error(format!("{} isn't a merged memo", merged_name)));
+        }
+        if names.len() != contents.len() {
+            return Err(tauri_error(format!(
+                "Can't split {} into {} memos, its content has {} parts",
+                merged_name,
+                names.len(),
+                contents.len()
+            )));
+        }
+        tx.execute("DELETE FROM memos WHERE name = ?1", [merged_name])
+            .map_err(sqlite_error)?;
+        for (name, content) in names.iter().zip(contents) {
+            if memo_exists(&tx, name)? {
+                return Err(tauri_error(format!("Memo {} exists already", name)));
+            }
+            tx.execute(
+                "INSERT INTO memos \
+                 (name, content, label, updated_at, created_at, content_hash) \
+                 VALUES (?1, ?2, ?3, datetime('now'), ?4, ?5)",
+                (
+                    name,
+                    content,
+                    &merged.label,
+                    &merged.created_at,
+                    db::content_hash(content),
+                ),
+            )
+            .map_err(sqlite_error)?;
+        }
+        let rows = select_rows(&tx, &names, state.rarray)?;
+        tx.commit().map_err(sqlite_error)?;
+        Ok(rows)
+    })
+}
+
+/// Proposes a label for the memo without applying it: the label with the most keywords
+/// in its content, according to the JSON file in `MEMOS_LABEL_KEYWORDS` that maps
+/// keywords to labels (`{"milk": "groceries"}`).
+#[tauri::command]
+fn suggest_label(
+    name: &str,
+    state: tauri::State<State>,
+) -> Result<Option<String>, Error> {
+    state.run_command("suggest_label", || {
+        let path = state
+            .config
+            .read()
+            .map_err(tauri_error)?
+            .label_keywords_path
+            .clone()
+            .ok_or_else(|| tauri_error("MEMOS_LABEL_KEYWORDS not set"))?;
+        let keywords: BTreeMap<String, String> = serde_json::from_slice(
+            &fs::read(&path).map_err(tauri_error)?,
+        )
+        .map_err(|err| {
+            tauri_error(format!(
+                "Couldn't read {}: {}",
+                Path::new(&path).display(),
+                err
+            ))
+        })?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let row = select_row(&guard, name)?;
+        let content = row.content.to_lowercase();
+        let words: HashSet<&str> = words(&content).collect();
+        let mut hits: BTreeMap<&str, usize> = BTreeMap::new();
+        for (keyword, label) in &keywords {
+            if words.contains(keyword.to_lowercase().as_str()) {
+                *hits.entry(label).or_default() += 1;
+            }
+        }
+        // On ties, the label that comes first alphabetically wins.
+        let best = hits
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(label, _)| label.to_string());
+        Ok(best)
+    })
+}
+
+/// Loads memos ordered by the modification time of their files, newest first. Memos
+/// without files come last.
+#[tauri::command]
+fn load_by_file_mtime(state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("load_by_file_mtime", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let dir = state.storage_dir()?;
+        let mut rows: Vec<_> = query_rows(&guard, "1 ORDER BY name ASC", ())?
+            .into_iter()
+            .map(|row| {
+                let modified = fs::metadata(storage::locate(
+                    &dir,
+                    &row.name,
+                    row.moved_to.as_deref(),
+                ))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+                (modified, row)
+            })
+            .collect();
+        // `None` sorts before `Some`, so reversing puts missing files last.
+        rows.sort_by(|(a, _), (b, _)| b.cmp(a));
+        Ok(rows.into_iter().map(|(_, row)| row).collect())
+    })
+}
+
+/// Deletes all memos and returns how many there were. `confirm_token` must be the
+/// current number of memos, so that a stray call doesn't wipe everything.
+#[tauri::command]
+fn wipe_all(confirm_token: &str, state: tauri::State<State>) -> Result<usize, Error> {
+    state.run_command("wipe_all", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let count: usize = tx
+            .query_row("SELECT count(*) FROM memos", (), |row| row.get(0))
+            .map_err(sqlite_error)?;
+        if confirm_token != count.to_string() {
+            return Err(tauri_error(format!(
+                "Pass the number of memos ({}) to confirm deleting them all",
+                count
+            )));
+        }
+        let deleted = tx.execute("DELETE FROM memos", ()).map_err(sqlite_error)?;
+        tx.commit().map_err(sqlite_error)?;
+        Ok(deleted)
+    })
+}
+
+/// Compares the contents of two memos line by line. Lines only in `name_a` are
+/// "removed" and lines only in `name_b` are "added".
+#[tauri::command]
+fn diff(
+    name_a: &str,
+    name_b: &str,
+    state: tauri::State<State>,
+) -> Result<Vec<DiffLine>, Error> {
+    state.run_command("diff", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let a = select_row(&guard, name_a)?;
+        let b = select_row(&guard, name_b)?;
+        let lines = similar::TextDiff::from_lines(&a.content, &b.content)
+            .iter_all_changes()
+            .map(|change| DiffLine {
+                kind: match change.tag() {
+                    similar::ChangeTag::Insert => DiffKind::Added,
+                    similar::ChangeTag::Delete => DiffKind::Removed,
+                    similar::ChangeTag::Equal => DiffKind::Unchanged,
+                },
+                text: change.value().trim_end_matches('\n').to_string(),
+            })
+            .collect();
+        Ok(lines)
+    })
+}
+
+/// Shows the database file in Finder (or the file manager on other systems), e.g. to
+/// back it up
+#[tauri::command]
+fn reveal_db(state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("reveal_db", || {
+        reveal_in_file_manager(&state.db_path).map_err(tauri_error)
+    })
+}
+
+/// Returns the path of the snapshot with the given name, refusing names that would
+/// point outside the snapshots directory.
+fn snapshot_path(state: &State, name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(Error::Message(format!("Invalid snapshot name: {name:?}")));
+    }
+    Ok(state.snapshots_dir().join(format!("{name}.sqlite")))
+}
+
+/// Saves a copy of the database as a snapshot with the given name, which can be brought
+/// back with [`restore_snapshot`].
+#[tauri::command]
+fn create_snapshot(name: &str, state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("create_snapshot", || {
+        let path = snapshot_path(&state, name)?;
+        if path.exists() {
+            return Err(Error::Message(format!("Snapshot {name} already exists")));
+        }
+        fs::create_dir_all(state.snapshots_dir()).map_err(tauri_error)?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        db::snapshot(&guard, &path).map_err(sqlite_error)
+    })
+}
+
+/// Lists the snapshots, newest first
+#[tauri::command]
+fn list_snapshots(state: tauri::State<State>) -> Result<Vec<Snapshot>, Error> {
+    state.run_command("list_snapshots", || {
+        let entries = match fs::read_dir(state.snapshots_dir()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(tauri_error(err)),
+        };
+        let mut snapshots = vec![];
+        for entry in entries {
+            let path = entry.map_err(tauri_error)?.path();
+            if path.extension().map_or(true, |ext| ext != "sqlite") {
+                continue;
+            }
+            let metadata = fs::metadata(&path).map_err(tauri_error)?;
+            let created_at = metadata.modified().map_err(tauri_error)?;
+            snapshots.push(Snapshot {
+                name: path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                created_at_millis: created_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as u64),
+                bytes: metadata.len(),
+            });
+        }
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at_millis));
+        Ok(snapshots)
+    })
+}
+
+/// Replaces the database with a snapshot. The connection is closed while the file is
+/// swapped and then opened again, so the current contents are lost unless they were
+/// snapshotted too. If the snapshot can't be opened, the database is left as it was.
+#[tauri::command]
+fn restore_snapshot(name: &str, state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("restore_snapshot", || {
+        state.check_writable()?;
+        let path = snapshot_path(&state, name)?;
+        if !path.is_file() {
+            return Err(Error::NotFound(path.display().to_string()));
+        }
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        // The snapshot is copied next to the database and opened there first, so that a
+        // snapshot that can't be used is rejected while the database is still in place.
+        let temp = state.db_path.with_file_name(".restoring.sqlite");
+        fs::copy(&path, &temp).map_err(tauri_error)?;
+        let checked = open_database(&temp, false).and_then(|(_, rarray)| {
+            if state.rarray && !rarray {
+                Err(String::from("Couldn't load array module"))
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(err) = checked {
+            let _ = fs::remove_file(&temp);
+            return Err(tauri_error(err));
+        }
+        let placeholder = Connection::open_in_memory().map_err(sqlite_error)?;
+        let old = std::mem::replace(&mut *guard, placeholder);
+        if let Err((conn, err)) = old.close() {
+            *guard = conn;
+            let _ = fs::remove_file(&temp);
+            return Err(sqlite_error(err));
+        }
+        for suffix in ["-wal", "-shm"] {
+            let mut journal = state.db_path.clone().into_os_string();
+            journal.push(suffix);
+            let _ = fs::remove_file(journal);
+        }
+        // Reopens whatever is at the database path, so that the app never keeps running
+        // on the placeholder, and returns `err`.
+        let reopen = |guard: &mut Connection, err: String| match open_database(
+            &state.db_path,
+            false,
+        ) {
+            Ok((conn, _)) => {
+                *guard = conn;
+                Error::Message(err)
+            }
+            Err(reopen_err) => Error::Message(format!("{err}. {reopen_err}")),
+        };
+        let previous = state.db_path.with_file_name(".before-restore.sqlite");
+        if let Err(err) = fs::rename(&state.db_path, &previous) {
+            let _ = fs::remove_file(&temp);
+            return Err(reopen(&mut guard, err.to_string()));
+        }
+        let result = fs::rename(&temp, &state.db_path)
+            .map_err(|err| err.to_string())
+            .and_then(|()| open_database(&state.db_path, false));
+        match result {
+            Ok((conn, _)) => {
+                *guard = conn;
+                let _ = fs::remove_file(&previous);
+                Ok(())
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                if let Err(rename_err) = fs::rename(&previous, &state.db_path) {
+                    return Err(reopen(&mut guard, format!("{err}. {rename_err}")));
+                }
+                Err(reopen(&mut guard, err))
+            }
+        }
+    })
+}
+
+/// Opens the folder containing `path` in the file manager, with the file selected where
+/// the file manager supports it.
+fn reveal_in_file_manager(path: &Path) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    } else if cfg!(target_os = "windows") {
+        let mut select = OsString::from("/select,");
+        select.push(path);
+        let mut command = Command::new("explorer");
+        command.arg(select);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(Path::new(".")));
+        command
+    };
+    command.spawn()?;
+    Ok(())
+}
+
+/// Merges memos recorded within `window_secs` of the previous one (by `created_at`),
+/// since those are usually one thought split by pauses. Memos whose `created_at` isn't a
+/// date SQLite understands are left alone. Returns the merged memos.
+#[tauri::command]
+fn auto_merge(window_secs: i64, state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("auto_merge", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let timestamps = {
+            let mut select_stmt = tx
+                .prepare(
+                    "SELECT name, \
+                     CAST(strftime('%s', created_at) AS INTEGER) AS created_secs \
+                     FROM memos WHERE strftime('%s', created_at) IS NOT NULL \
+                     ORDER BY created_secs ASC",
+                )
+                .map_err(tauri_error)?;
+            let timestamps = select_stmt
+                .query_map((), |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })
+                .map_err(tauri_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(tauri_error)?;
+            timestamps
+        };
+        let mut groups: Vec<Vec<&str>> = Vec::new();
+        let mut previous = None;
+        for (name, time) in &timestamps {
+            match (groups.last_mut(), previous) {
+                (Some(group), Some(previous)) if time - previous <= window_secs => {
+                    group.push(name)
+                }
+                _ => groups.push(vec![name]),
+            }
+            previous = Some(*time);
+        }
+        let mut merged = Vec::new();
+        for group in groups.iter().filter(|group| group.len() > 1) {
+            merged.push(merge_rows(
+                &tx,
+                group,
+                &MergeOptions::default(),
+                state.rarray,
+            )?);
+        }
+        tx.commit().map_err(sqlite_error)?;
+        Ok(merged)
+    })
+}
+
+/// Adds a "memos: test" to-do to Things, to check that the integration works without
+/// touching any memos
+#[tauri::command]
+fn test_things(state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("test_things", || {
+        check_things_installed()?;
+        let item = things3::Item::Todo(things3::Todo {
+            title: String::from("memos: test"),
+            notes: Some(String::from(
+                "Added by the memos app to test the integration",
+            )),
+            tags: Vec::new(),
+            checklist_items: Vec::new(),
+        });
+        let url = things3::json_url(&[item], true, None).map_err(tauri_error)?;
+        let status = Command::new("open")
+            .arg(url.as_str())
+            .status()
+            .map_err(tauri_error)?;
+        if !status.success() {
+            return Err(tauri_error(format!(
+                "Couldn't open the Things URL: {}",
+                status
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// Runs the configured VLC with `--version` to check that playback can work, and returns
+/// the version line, like "VLC media player 3.0.18 Vetinari"
+#[tauri::command]
+fn test_vlc(state: tauri::State<State>) -> Result<String, Error> {
+    state.run_command("test_vlc", || {
+        let vlc_path = state.vlc_path()?;
+        let output =
+            Command::new(&vlc_path)
+                .arg("--version")
+                .output()
+                .map_err(|err| {
+                    tauri_error(format!("Couldn't run {}: {}", vlc_path.display(), err))
+                })?;
+        if !output.status.success() {
+            return Err(tauri_error(format!(
+                "{} --version failed: {}",
+                vlc_path.display(),
+                output.status
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        stdout
+            .lines()
+            .chain(stderr.lines())
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(String::from)
+            .ok_or_else(|| {
+                tauri_error(format!("{} printed no version", vlc_path.display()))
+            })
+    })
+}
+
+/// Counts memos created on each day (in local time), for an activity heatmap
+#[tauri::command]
+fn activity(state: tauri::State<State>) -> Result<Vec<(String, i64)>, Error> {
+    state.run_command("activity", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare(
+                "SELECT date(created_at, 'localtime') AS day, count(*) FROM memos \
+                 WHERE created_at IS NOT NULL GROUP BY day ORDER BY day ASC",
+            )
+            .map_err(tauri_error)?;
+        let days = select_stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(tauri_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(tauri_error)?;
+        Ok(days)
+    })
+}
+
+/// Returns the average content length (in characters) of each label's memos, sorted by
+/// label. Memos without a label are counted under "unknown".
+#[tauri::command]
+fn label_avg_length(state: tauri::State<State>) -> Result<Vec<(String, f64)>, Error> {
+    state.run_command("label_avg_length", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare(&format!(
+                "SELECT coalesce({}, 'unknown') AS known_label, avg(length(content)) \
+                 FROM memos GROUP BY known_label ORDER BY known_label ASC",
+                KNOWN_LABEL
+            ))
+            .map_err(tauri_error)?;
+        let averages = select_stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(tauri_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(tauri_error)?;
+        Ok(averages)
+    })
+}
+
+/// Loads all memos with the content cut to `preview_len` characters plus "…", for
+/// showing a compact list
+#[tauri::command]
+fn load_previews(
+    preview_len: usize,
+    state: tauri::State<State>,
+) -> Result<Vec<Preview>, Error> {
+    state.run_command("load_previews", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let previews = query_rows(&guard, "1 ORDER BY name ASC", ())?
+            .into_iter()
+            .map(|mut row| {
+                let cut = row.content.char_indices().nth(preview_len).map(|(i, _)| i);
+                if let Some(cut) = cut {
+                    row.content.truncate(cut);
+                    row.content.push('…');
+                }
+                Preview {
+                    row,
+                    truncated: cut.is_some(),
+                }
+            })
+            .collect();
+        Ok(previews)
+    })
+}
+
+/// Replaces `find` with `replace` in the content of all memos, e.g. to fix a name that
+/// was transcribed wrong. Matches literally, ignoring case unless `case_sensitive`.
+/// Returns the number of memos changed.
+#[tauri::command]
+fn replace_all(
+    find: &str,
+    replace: &str,
+    case_sensitive: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<usize, Error> {
+    state.run_command("replace_all", || {
+        if find.is_empty() {
+            return Err(tauri_error("Nothing to find"));
+        }
+        let case_sensitive = case_sensitive.unwrap_or(false);
+        replace_in_contents(&state, |content| {
+            if case_sensitive {
+                content.replace(find, replace)
+            } else {
+                replace_ignore_case(content, find, replace)
+            }
+        })
+    })
+}
+
+/// Rewrites the content of every memo with `edit` in one transaction, and returns how
+/// many memos actually changed.
+fn replace_in_contents(
+    state: &State,
+    edit: impl Fn(&str) -> String,
+) -> Result<usize, Error> {
+    state.check_writable()?;
+    let db_conn = state.db_conn.clone();
+    let mut guard = db_conn.lock().map_err(tauri_error)?;
+    let tx = guard.transaction().map_err(sqlite_error)?;
+    let mut changed = 0;
+    for row in query_rows(&tx, "1", ())? {
+        let new_content = edit(&row.content);
+        if new_content != row.content {
+            tx.execute(
+                "UPDATE memos SET content = ?1, updated_at = datetime('now'), \
+                 content_hash = ?3 WHERE name = ?2",
+                [&new_content, &row.name, &db::content_hash(&new_content)],
+            )
+            .map_err(sqlite_error)?;
+            changed += 1;
+        }
+    }
+    tx.commit().map_err(sqlite_error)?;
+    Ok(changed)
+}
+
+/// Like [`str::replace`], but compares characters case-insensitively.
+fn replace_ignore_case(text: &str, find: &str, replace: &str) -> String {
+    let find: Vec<char> = find.chars().collect();
+    let chars: Vec<char> = text.chars().collect();
+    let same = |a: &char, b: &char| a.to_lowercase().eq(b.to_lowercase());
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let rest = &chars[i..];
+        if !find.is_empty()
+            && rest.len() >= find.len()
+            && rest.iter().zip(&find).all(|(a, b)| same(a, b))
+        {
+            result.push_str(replace);
+            i += find.len();
+        } else {
+            result.push(chars[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+/// Replaces matches of the regex `pattern` in the content of all memos. `replacement`
+/// can refer to capture groups as `$1` or `$name`. Returns the number of memos changed.
+#[tauri::command]
+fn replace_regex(
+    pattern: &str,
+    replacement: &str,
+    state: tauri::State<State>,
+) -> Result<usize, Error> {
+    state.run_command("replace_regex", || {
+        let regex = regex::Regex::new(pattern).map_err(Error::InvalidRegex)?;
+        replace_in_contents(&state, |content| {
+            regex.replace_all(content, replacement).into_owned()
+        })
+    })
+}
+
+/// Loads memos whose content matches the regex `pattern`
+#[tauri::command]
+fn search_regex(pattern: &str, state: tauri::State<State>) -> Result<Vec<Row>, Error> {
+    state.run_command("search_regex", || {
+        let regex = regex::Regex::new(pattern).map_err(Error::InvalidRegex)?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare(&format!(
+                "SELECT {} FROM memos ORDER BY name ASC",
+                Row::COLUMNS
+            ))
+            .map_err(tauri_error)?;
+        let mut rows = Vec::new();
+        for row in select_stmt
+            .query_map((), Row::from_sql)
+            .map_err(tauri_error)?
+        {
+            let row = row.map_err(tauri_error)?;
+            if regex.is_match(&row.content) {
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    })
+}
+
+/// Marks memos as pushed to Things without pushing them, e.g. when they were handled
+/// elsewhere, so that `add_to_things` skips them. Returns how many memos were marked.
+#[tauri::command]
+fn mark_pushed(names: Vec<&str>, state: tauri::State<State>) -> Result<usize, Error> {
+    state.run_command("mark_pushed", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        set_pushed(&guard, &names, state.rarray)
+    })
+}
+
+/// Splits text into words at whitespace and punctuation.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// Counts distinct words (ignoring case) across all memos
+#[tauri::command]
+fn vocabulary(state: tauri::State<State>) -> Result<usize, Error> {
+    state.run_command("vocabulary", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare("SELECT content FROM memos")
+            .map_err(tauri_error)?;
+        let mut rows = select_stmt.query(()).map_err(tauri_error)?;
+        let mut vocabulary = HashSet::new();
+        while let Some(row) = rows.next().map_err(tauri_error)? {
+            let content = row
+                .get_ref(0)
+                .map_err(tauri_error)?
+                .as_str()
+                .unwrap_or_default()
+                .to_lowercase();
+            vocabulary.extend(words(&content).map(String::from));
+        }
+        Ok(vocabulary.len())
+    })
+}
+
+/// Words left out of [`top_words`] unless `MEMOS_STOPWORDS` points to a file with a
+/// different list.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "do", "for", "from", "have",
+    "i", "if", "in", "is", "it", "just", "like", "me", "my", "of", "on", "or", "so",
+    "that", "the", "this", "to", "was", "we", "with", "you",
+];
+
+/// Returns the `limit` most frequent words across all memos with their counts, most
+/// frequent first. Stopwords are skipped: the built-in English ones, or the
+/// whitespace-separated words from the file in `MEMOS_STOPWORDS`.
+#[tauri::command]
+fn top_words(
+    limit: usize,
+    state: tauri::State<State>,
+) -> Result<Vec<(String, usize)>, Error> {
+    state.run_command("top_words", || {
+        let stopwords_path = state
+            .config
+            .read()
+            .map_err(tauri_error)?
+            .stopwords_path
+            .clone();
+        let custom_stopwords = match stopwords_path {
+            Some(path) => Some(
+                fs::read_to_string(path)
+                    .map_err(tauri_error)?
+                    .to_lowercase(),
+            ),
+            None => None,
+        };
+        let stopwords: HashSet<&str> = match &custom_stopwords {
+            Some(text) => text.split_whitespace().collect(),
+            None => STOPWORDS.iter().copied().collect(),
+        };
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare("SELECT content FROM memos")
+            .map_err(tauri_error)?;
+        let mut rows = select_stmt.query(()).map_err(tauri_error)?;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        while let Some(row) = rows.next().map_err(tauri_error)? {
+            let content = row
+                .get_ref(0)
+                .map_err(tauri_error)?
+                .as_str()
+                .unwrap_or_default()
+                .to_lowercase();
+            for word in words(&content).filter(|word| !stopwords.contains(word)) {
+                *counts.entry(word.to_string()).or_default() += 1;
+            }
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        counts.truncate(limit);
+        Ok(counts)
+    })
+}
+
+/// Renames all memos to `<prefix>-0001.m4a` etc. in name order, keeping extensions, and
+/// with `rename_files` renames their files too. Returns the old and new names. If a file
+/// can't be renamed, nothing is changed.
+#[tauri::command]
+fn renumber(
+    prefix: &str,
+    rename_files: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<Vec<(String, String)>, Error> {
+    state.run_command("renumber", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let names: Vec<String> = query_rows(&tx, "1 ORDER BY name ASC", ())?
+            .into_iter()
+            .map(|row| row.name)
+            .collect();
+        let width = names.len().to_string().len().max(4);
+        let renames: Vec<(String, String)> = names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let extension = Path::new(&name)
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                let new_name = format!("{}-{:0width$}{}", prefix, i + 1, extension);
+                (name, new_name)
+            })
+            .collect();
+        // Through temporary names, so that new names don't clash with old ones.
+        for (i, (name, _)) in renames.iter().enumerate() {
+            tx.execute(
+                "UPDATE memos SET name = ?1 WHERE name = ?2",
+                (format!("\u{1}renumber-{}", i), name),
+            )
+            .map_err(sqlite_error)?;
+        }
+        for (i, (_, new_name)) in renames.iter().enumerate() {
+            tx.execute(
+                "UPDATE memos SET name = ?1 WHERE name = ?2",
+                (new_name, format!("\u{1}renumber-{}", i)),
+            )
+            .map_err(sqlite_error)?;
+        }
+        if rename_files.unwrap_or(false) {
+            let dir = state.storage_dir()?;
+            storage::rename_all(&dir, &renames)
+                .map_err(|err| tauri_error(format!("Couldn't rename files: {}", err)))?;
+        }
+        tx.commit().map_err(sqlite_error)?;
+        Ok(renames)
+    })
+}
+
+/// Returns the last invocations of commands, oldest first, for reproducing bug reports
+#[tauri::command]
+fn command_history(state: tauri::State<State>) -> Result<Vec<CommandLogEntry>, Error> {
+    state.run_command("command_history", || {
+        let history = state.command_history.lock().map_err(tauri_error)?;
+        Ok(history.iter().cloned().collect())
+    })
+}
+
+/// Returns names that are empty or shared by several memos. Empty and missing names are
+/// returned as "".
+#[tauri::command]
+fn validate_names(state: tauri::State<State>) -> Result<Vec<String>, Error> {
+    state.run_command("validate_names", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare(
+                "SELECT coalesce(name, '') FROM memos GROUP BY name \
+                 HAVING count(*) > 1 OR trim(coalesce(name, '')) = '' ORDER BY name",
+            )
+            .map_err(tauri_error)?;
+        let names = select_stmt
+            .query_map((), |row| row.get(0))
+            .map_err(tauri_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(tauri_error)?;
+        Ok(names)
+    })
+}
+
+/// Fixes the names reported by [`validate_names`]: memos with empty names are renamed to
+/// "untitled", and all but the first memo with a duplicate name get a name like
+/// `memo (2).m4a`. Files aren't renamed. Afterwards names are kept unique, see
+/// [`db::create_name_index`]. Returns the new names.
+#[tauri::command]
+fn fix_names(state: tauri::State<State>) -> Result<Vec<String>, Error> {
+    state.run_command("fix_names", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let to_fix = {
+            let mut select_stmt = tx
+                .prepare(
+                    "SELECT rowid, coalesce(name, '') FROM memos AS m \
+                     WHERE trim(coalesce(name, '')) = '' OR EXISTS( \
+                         SELECT 1 FROM memos WHERE name = m.name AND rowid < m.rowid) \
+                     ORDER BY rowid",
+                )
+                .map_err(sqlite_error)?;
+            let to_fix = select_stmt
+                .query_map((), |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(sqlite_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(sqlite_error)?;
+            to_fix
+        };
+        let mut new_names = Vec::new();
+        for (rowid, name) in to_fix {
+            let base = if name.trim().is_empty() {
+                "untitled"
+            } else {
+                &name
+            };
+            let new_name = unused_name(&tx, base)?;
+            tx.execute(
+                "UPDATE memos SET name = ?1 WHERE rowid = ?2",
+                (&new_name, rowid),
+            )
+            .map_err(sqlite_error)?;
+            new_names.push(new_name);
+        }
+        db::create_name_index(&tx).map_err(sqlite_error)?;
+        tx.commit().map_err(sqlite_error)?;
+        Ok(new_names)
+    })
+}
+
+/// Recomputes `content_hash` of memos whose content was changed outside the app, or of
+/// all memos with `all`. Returns how many memos were updated.
+#[tauri::command]
+fn recompute_hashes(
+    all: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<usize, Error> {
+    state.run_command("recompute_hashes", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let updated = db::recompute_content_hashes(&tx, !all.unwrap_or(false))
+            .map_err(sqlite_error)?;
+        tx.commit().map_err(sqlite_error)?;
+        Ok(updated)
+    })
+}
+
+/// Joins the contents of the memos in name order with `separator` (a blank line by
+/// default), for copying into an email or chat. With `with_labels`, each content is
+/// prefixed by its label, like "work: call Bob". Unlike [`merge`], nothing is changed.
+#[tauri::command]
+fn share_text(
+    names: Vec<&str>,
+    separator: Option<String>,
+    with_labels: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<String, Error> {
+    state.run_command("share_text", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let rows_vec = select_rows(&guard, &names, state.rarray)?;
+        let texts: Vec<String> = rows_vec
+            .iter()
+            .map(|row| match row.known_label() {
+                Some(label) if with_labels.unwrap_or(false) => {
+                    format!("{}: {}", label, row.content)
+                }
+                _ => row.content.clone(),
+            })
+            .collect();
+        Ok(texts.join(separator.as_deref().unwrap_or("\n\n")))
+    })
+}
+
+/// Detects the language of a memo's content and returns its ISO 639-3 code, like "eng".
+/// Returns `None` if the content is too short or the detection isn't reliable.
+#[tauri::command]
+fn detect_language(
+    name: &str,
+    state: tauri::State<State>,
+) -> Result<Option<String>, Error> {
+    state.run_command("detect_language", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let row = select_row(&guard, name)?;
+        if words(&row.content).count() < LANGUAGE_MIN_WORDS {
+            return Ok(None);
+        }
+        Ok(whatlang::detect(&row.content)
+            .filter(whatlang::Info::is_reliable)
+            .map(|info| info.lang().code().to_string()))
+    })
+}
+
+/// Writes an M3U playlist with the memos' audio files, in the given order. Missing files
+/// are left out and reported as failures.
+#[tauri::command]
+fn export_playlist(
+    names: Vec<&str>,
+    path: &str,
+    state: tauri::State<State>,
+) -> Result<BatchResult, Error> {
+    state.run_command("export_playlist", || {
+        let dir = state.storage_dir()?;
+        let guard = state.db_conn.lock().map_err(tauri_error)?;
+        let mut result = BatchResult::default();
+        let mut playlist = String::from("#EXTM3U\n");
+        for name in names {
+            let file = memo_path(&guard, &dir, name)?;
+            if file.is_file() {
+                playlist.push_str(&format!("{}\n", file.display()));
+                result.succeeded.push(name.to_string());
+            } else {
+                result.failed.push(Failure {
+                    name: name.to_string(),
+                    reason: format!("File {} doesn't exist", file.display()),
+                });
+            }
+        }
+        fs::write(path, playlist).map_err(tauri_error)?;
+        Ok(result)
+    })
+}
+
+/// Sets labels by `(name, label)` pairs in one transaction, e.g. after reviewing a batch
+/// of memos. Returns how many memos were updated; names that don't exist are ignored.
+#[tauri::command]
+fn set_labels_map(
+    assignments: Vec<(String, String)>,
+    state: tauri::State<State>,
+) -> Result<usize, Error> {
+    state.run_command("set_labels_map", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            let mut updated = 0;
+            {
+                let mut update_stmt = tx
+                    .prepare("UPDATE memos SET label = ?2 WHERE name = ?1")
+                    .map_err(sqlite_error)?;
+                for (name, label) in &assignments {
+                    updated +=
+                        update_stmt.execute([name, label]).map_err(sqlite_error)?;
+                }
+            }
+            tx.commit().map_err(sqlite_error)?;
+            Ok(updated)
+        })
+    })
+}
+
+/// Bookmarks a point `secs` into a memo's recording, with an optional label
+#[tauri::command]
+fn add_bookmark(
+    name: &str,
+    secs: f64,
+    label: Option<String>,
+    state: tauri::State<State>,
+) -> Result<(), Error> {
+    state.run_command("add_bookmark", || {
+        state.check_writable()?;
+        if !secs.is_finite() || secs < 0.0 {
+            return Err(tauri_error(format!("Invalid bookmark position {}", secs)));
+        }
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        if !memo_exists(&guard, name)? {
+            return Err(tauri_error(format!("Memo {} doesn't exist", name)));
+        }
+        guard
+            .execute(
+                "INSERT INTO bookmarks (name, secs, label) VALUES (?1, ?2, ?3)",
+                (name, secs, label),
+            )
+            .map_err(sqlite_error)?;
+        Ok(())
+    })
+}
+
+/// Lists a memo's bookmarks by position. `open` takes an index into this list.
+#[tauri::command]
+fn list_bookmarks(
+    name: &str,
+    state: tauri::State<State>,
+) -> Result<Vec<Bookmark>, Error> {
+    state.run_command("list_bookmarks", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        query_bookmarks(&guard, name)
+    })
+}
+
+fn query_bookmarks(conn: &Connection, name: &str) -> Result<Vec<Bookmark>, Error> {
+    let mut select_stmt = conn
+        .prepare("SELECT secs, label FROM bookmarks WHERE name = ?1 ORDER BY secs, rowid")
+        .map_err(tauri_error)?;
+    let bookmarks = select_stmt
+        .query_map([name], |row| {
+            Ok(Bookmark {
+                secs: row.get(0)?,
+                label: row.get(1)?,
+            })
+        })
+        .map_err(tauri_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(tauri_error)?;
+    Ok(bookmarks)
+}
+
+/// Deletes memos created more than `days` days ago, and returns how many there were.
+/// Memos without `created_at` are kept.
+#[tauri::command]
+fn purge_older_than(days: i64, state: tauri::State<State>) -> Result<usize, Error> {
+    state.run_command("purge_older_than", || {
+        state.check_writable()?;
+        if days < 0 {
+            return Err(tauri_error(format!("Invalid number of days {}", days)));
+        }
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            let deleted = tx
+                .execute(
+                    "DELETE FROM memos WHERE created_at < datetime('now', ?1)",
+                    [format!("-{} days", days)],
+                )
+                .map_err(sqlite_error)?;
+            tx.commit().map_err(sqlite_error)?;
+            Ok(deleted)
+        })
+    })
+}
+
+/// Reads `.env` and the environment again, so that changes to `VOICE_MEMOS_STORAGE` and
+/// `VLC_PATH` take effect without restarting. Like at startup, the environment overrides
+/// `.env`, and variables removed from `.env` are unset.
+///
+/// If the storage directory changes, the webview can't load files from the old one
+/// anymore. Switching back to it needs a restart.
+#[tauri::command]
+fn reload_config(app: tauri::AppHandle, state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("reload_config", || {
+        let old_dir = state.storage_dir().ok();
+        {
+            let mut keys = state.env_file_keys.lock().map_err(tauri_error)?;
+            *keys = config::load_env_file(&keys).map_err(tauri_error)?;
+            let mut config = state.config.write().map_err(tauri_error)?;
+            *config = config::Config::from_env();
+        }
+        let new_dir = state.storage_dir().ok();
+        if old_dir == new_dir {
+            return Ok(());
+        }
+        let scope = app.asset_protocol_scope();
+        if let Some(old_dir) = old_dir {
+            // Allowed directories can't be removed from the scope, but forbidden ones win
+            if let Err(err) = scope.forbid_directory(&old_dir, false) {
+                eprintln!(
+                    "Couldn't forbid loading files from {}: {err}",
+                    old_dir.display()
+                );
+            }
+        }
+        allow_storage_dir(&app);
+        match new_dir {
+            Some(dir) if !scope.is_allowed(&dir) => Err(tauri_error(format!(
+                "Files in {} can only be played after restarting the app",
+                dir.display()
+            ))),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Lets the webview load files from the storage directory, see [`asset_url`].
+fn allow_storage_dir(app: &tauri::AppHandle) {
+    let Ok(dir) = app.state::<State>().storage_dir() else {
+        return;
+    };
+    if let Err(err) = app.asset_protocol_scope().allow_directory(&dir, false) {
+        eprintln!("Couldn't allow loading files from {}: {err}", dir.display());
+    }
+}
+
+/// Returns names of the other memos whose content mentions `name` (ignoring ASCII case)
+#[tauri::command]
+fn backlinks(name: &str, state: tauri::State<State>) -> Result<Vec<String>, Error> {
+    state.run_command("backlinks", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let pattern = format!(
+            "%{}%",
+            name.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut select_stmt = guard
+            .prepare(
+                "SELECT name FROM memos WHERE content LIKE ?1 ESCAPE '\\' AND name != ?2 \
+                 ORDER BY name ASC",
+            )
+            .map_err(tauri_error)?;
+        let names = select_stmt
+            .query_map([&pattern, name], |row| row.get(0))
+            .map_err(tauri_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(tauri_error)?;
+        Ok(names)
+    })
+}
+
+/// Trims and lowercases all labels, so that "Work", "work " and "WORK" become one label.
+/// Blank labels become "unknown". Returns how many memos changed.
+#[tauri::command]
+fn normalize_labels(state: tauri::State<State>) -> Result<usize, Error> {
+    state.run_command("normalize_labels", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            let labels = {
+                let mut select_stmt = tx
+                    .prepare("SELECT DISTINCT label FROM memos WHERE label IS NOT NULL")
+                    .map_err(sqlite_error)?;
+                let labels = select_stmt
+                    .query_map((), |row| row.get::<_, String>(0))
+                    .map_err(sqlite_error)?
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(sqlite_error)?;
+                labels
+            };
+            let mut changed = 0;
+            for label in labels {
+                let normalized = match label.trim() {
+                    "" => String::from("unknown"),
+                    trimmed => trimmed.to_lowercase(),
+                };
+                if normalized != label {
+                    changed += tx
+                        .execute(
+                            "UPDATE memos SET label = ?1 WHERE label = ?2",
+                            [&normalized, &label],
+                        )
+                        .map_err(sqlite_error)?;
+                }
+            }
+            tx.commit().map_err(sqlite_error)?;
+            Ok(changed)
+        })
+    })
+}
+
+/// Returns the previous contents of a memo, newest first. Contents are recorded whenever
+/// they change, and merging records the contents of the merged memos.
+#[tauri::command]
+fn history(name: &str, state: tauri::State<State>) -> Result<Vec<HistoryEntry>, Error> {
+    state.run_command("history", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let mut select_stmt = guard
+            .prepare(
+                "SELECT id, content, replaced_at FROM memo_history WHERE name = ?1 \
+                 ORDER BY id DESC",
+            )
+            .map_err(tauri_error)?;
+        let entries = select_stmt
+            .query_map([name], |row| {
+                Ok(HistoryEntry {
+                    id: row.get(0)?,
+                    content: row.get(1)?,
+                    replaced_at: row.get(2)?,
+                })
+            })
+            .map_err(tauri_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(tauri_error)?;
+        Ok(entries)
+    })
+}
+
+/// Restores a previous content of a memo from its [`history`]. The current content is
+/// recorded in the history too, so reverting can be undone.
+#[tauri::command]
+fn revert(name: &str, version_id: i64, state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("revert", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            let content: String = tx
+                .query_row(
+                    "SELECT content FROM memo_history WHERE id = ?1 AND name = ?2",
+                    (version_id, name),
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(sqlite_error)?
+                .ok_or_else(|| {
+                    tauri_error(format!("Memo {} has no version {}", name, version_id))
+                })?;
+            tx.execute(
+                "UPDATE memos SET content = ?1, updated_at = datetime('now'), \
+                 content_hash = ?3 WHERE name = ?2",
+                [&content, name, &db::content_hash(&content)],
+            )
+            .map_err(sqlite_error)?;
+            tx.commit().map_err(sqlite_error)
+        })
+    })
+}
+
+/// Writes the memos' audio files into a zip archive at `path`, plus with `manifest` a
+/// `manifest.json` with their rows. Missing files are left out and reported as failures.
+/// Runs off the main thread, since archives can get large, and can be stopped with
+/// [`cancel_operation`], which leaves a valid archive with the files written so far.
+#[tauri::command(async)]
+fn export_audio_zip(
+    names: Vec<String>,
+    path: String,
+    manifest: Option<bool>,
+    state: tauri::State<'_, State>,
+) -> Result<BatchResult, Error> {
+    state.run_command("export_audio_zip", || {
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let dir = state.storage_dir()?;
+        let (rows_vec, files) = {
+            let db_conn = state.db_conn.clone();
+            let guard = db_conn.lock().map_err(tauri_error)?;
+            let files = names
+                .iter()
+                .map(|name| memo_path(&guard, &dir, name))
+                .collect::<Result<Vec<_>, _>>()?;
+            (select_rows(&guard, &names, state.rarray)?, files)
+        };
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).map_err(tauri_error)?);
+        // Audio is compressed already, so the files are only stored.
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        let mut result = BatchResult::default();
+        state.begin_operation();
+        for (i, (name, file)) in names.into_iter().zip(files).enumerate() {
+            if state.is_cancelled() {
+                result.cancelled_after = Some(i);
+                break;
+            }
+            let mut source = match fs::File::open(&file) {
+                Ok(source) => source,
+                Err(err) => {
+                    result.failed.push(Failure {
+                        name: name.to_string(),
+                        reason: format!("Couldn't read {}: {}", file.display(), err),
+                    });
+                    continue;
+                }
+            };
+            zip.start_file(name, options).map_err(tauri_error)?;
+            std::io::copy(&mut source, &mut zip).map_err(tauri_error)?;
+            result.succeeded.push(name.to_string());
+        }
+        if manifest.unwrap_or(false) {
+            zip.start_file("manifest.json", options)
+                .map_err(tauri_error)?;
+            serde_json::to_writer_pretty(&mut zip, &rows_vec).map_err(tauri_error)?;
+        }
+        zip.finish().map_err(tauri_error)?;
+        Ok(result)
+    })
+}
+
+/// Returns names of memos whose file exists but is empty or doesn't start like an audio
+/// file of its type. Memos without files are skipped.
+#[tauri::command]
+fn broken_files(state: tauri::State<State>) -> Result<Vec<String>, Error> {
+    state.run_command("broken_files", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let dir = state.storage_dir()?;
+        let mut broken = Vec::new();
+        for row in query_rows(&guard, "1 ORDER BY name ASC", ())? {
+            let path = storage::locate(&dir, &row.name, row.moved_to.as_deref());
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            // Empty files are caught without opening them.
+            if metadata.len() == 0 || !audio::has_valid_header(&path).unwrap_or(false) {
+                broken.push(row.name);
+            }
+        }
+        Ok(broken)
+    })
+}
+
+/// How similar a file name has to be to a memo's name to be suggested for it, from 0 to 1.
+const NAME_MATCH_MIN_RATIO: f32 = 0.6;
+
+/// How many files to suggest per memo.
+const NAME_MATCH_SUGGESTIONS: usize = 3;
+
+/// Lists memos whose file is missing, each with the files without a memo that are named
+/// similarly, e.g. after the file was renamed by hand. Only suggests, nothing is renamed.
+/// Merged memos are skipped, since their names aren't file names.
+#[tauri::command]
+fn name_file_mismatches(state: tauri::State<State>) -> Result<Vec<NameMismatch>, Error> {
+    state.run_command("name_file_mismatches", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let dir = state.storage_dir()?;
+        let orphans: Vec<String> = find_orphaned_files(&guard, &dir)?
+            .iter()
+            .map(|file| storage::display_name(file))
+            .collect();
+        let mut mismatches = Vec::new();
+        for row in query_rows(&guard, "merged_from IS NULL ORDER BY name ASC", ())? {
+            if storage::resolve(&dir, &row.name).is_file() {
+                continue;
+            }
+            let name = row.name.to_lowercase();
+            let mut scored: Vec<(f32, &String)> = orphans
+                .iter()
+                .map(|file| {
+                    let ratio = similar::TextDiff::from_chars(
+                        name.as_str(),
+                        &file.to_lowercase(),
+                    )
+                    .ratio();
+                    (ratio, file)
+                })
+                .filter(|(ratio, _)| *ratio >= NAME_MATCH_MIN_RATIO)
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+            mismatches.push(NameMismatch {
+                name: row.name,
+                suggestions: scored
+                    .into_iter()
+                    .take(NAME_MATCH_SUGGESTIONS)
+                    .map(|(_, file)| file.clone())
+                    .collect(),
+            });
+        }
+        Ok(mismatches)
+    })
+}
+
+/// Merges all memos matching a search query into one, like [`search`] followed by
+/// [`merge`], and returns the merged memo. Fails if fewer than two memos match.
+#[tauri::command]
+fn merge_search_results(query: &str, state: tauri::State<State>) -> Result<Row, Error> {
+    state.run_command("merge_search_results", || {
+        state.check_writable()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        retry_busy(|| {
+            let tx = guard.transaction().map_err(sqlite_error)?;
+            let rows_vec = search_rows(&tx, query)?;
+            if rows_vec.len() < 2 {
+                return Err(tauri_error(format!(
+                    "Only {} memos match \"{}\", need at least two to merge",
+                    rows_vec.len(),
+                    query
+                )));
+            }
+            let names: Vec<&str> = rows_vec.iter().map(|row| row.name.as_str()).collect();
+            let merged = merge_rows(&tx, &names, &MergeOptions::default(), state.rarray)?;
+            tx.commit().map_err(sqlite_error)?;
+            Ok(merged)
+        })
+    })
+}
+
+/// Characters that JavaScript's `encodeURIComponent` escapes.
+const URI_COMPONENT: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// Returns the asset protocol URL of a memo's file, same as `convertFileSrc` in the
+/// frontend, so that `<audio>` can stream and seek it without loading the whole file.
+/// Files moved out of the storage directory are added to the asset scope.
+#[tauri::command]
+fn asset_url(
+    name: &str,
+    app: tauri::AppHandle,
+    state: tauri::State<State>,
+) -> Result<String, Error> {
+    state.run_command("asset_url", || {
+        let dir = state.storage_dir()?;
+        let path = {
+            let guard = state.db_conn.lock().map_err(tauri_error)?;
+            memo_path(&guard, &dir, name)?
+        };
+        if !path.is_file() {
+            return Err(Error::NotFound(path.display().to_string()));
+        }
+        if !path.starts_with(&dir) {
+            app.asset_protocol_scope()
+                .allow_file(&path)
+                .map_err(tauri_error)?;
+        }
+        let encoded =
+            percent_encoding::utf8_percent_encode(&path.to_string_lossy(), URI_COMPONENT)
+                .to_string();
+        Ok(if cfg!(windows) {
+            format!("https://asset.localhost/{}", encoded)
+        } else {
+            format!("asset://localhost/{}", encoded)
+        })
+    })
+}
+
+/// Counts memos created today (in local time), in the last 7 days and in the last 30
+/// days
+#[tauri::command]
+fn recent_counts(state: tauri::State<State>) -> Result<RecentCounts, Error> {
+    state.run_command("recent_counts", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        guard
+            .query_row(
+                "SELECT \
+                 count(*) FILTER (WHERE date(created_at, 'localtime') = date('now', 'localtime')), \
+                 count(*) FILTER (WHERE created_at >= datetime('now', '-7 days')), \
+                 count(*) FILTER (WHERE created_at >= datetime('now', '-30 days')) \
+                 FROM memos",
+                (),
+                |row| {
+                    Ok(RecentCounts {
+                        today: row.get(0)?,
+                        last_7_days: row.get(1)?,
+                        last_30_days: row.get(2)?,
+                    })
+                },
+            )
+            .map_err(tauri_error)
+    })
+}
+
+/// Returns the transcript kept next to a memo's file, like `memo.txt` or `memo.vtt` for
+/// `memo.m4a`, if there is one
+#[tauri::command]
+fn load_sidecar(name: &str, state: tauri::State<State>) -> Result<Option<String>, Error> {
+    state.run_command("load_sidecar", || {
+        storage::read_sidecar(&state.storage_dir()?, name).map_err(tauri_error)
+    })
+}
+
+/// Copies transcripts from [`load_sidecar`] into the content of memos that have none, or
+/// of all memos that have a transcript with `overwrite`. Returns the updated memos.
+#[tauri::command]
+fn import_sidecars(
+    overwrite: Option<bool>,
+    state: tauri::State<State>,
+) -> Result<Vec<String>, Error> {
+    state.run_command("import_sidecars", || {
+        state.check_writable()?;
+        let dir = state.storage_dir()?;
+        let db_conn = state.db_conn.clone();
+        let mut guard = db_conn.lock().map_err(tauri_error)?;
+        let tx = guard.transaction().map_err(sqlite_error)?;
+        let filter = if overwrite.unwrap_or(false) {
+            "1 ORDER BY name ASC"
+        } else {
+            "trim(content) = '' ORDER BY name ASC"
+        };
+        let mut updated = Vec::new();
+        for row in query_rows(&tx, filter, ())? {
+            let Some(text) =
+                storage::read_sidecar(&dir, &row.name).map_err(tauri_error)?
+            else {
+                continue;
+            };
+            let text = text.trim();
+            if text == row.content {
+                continue;
+            }
+            tx.execute(
+                "UPDATE memos SET content = ?1, updated_at = datetime('now'), \
+                 content_hash = ?3 WHERE name = ?2",
+                [text, &row.name, &db::content_hash(text)],
+            )
+            .map_err(sqlite_error)?;
+            updated.push(row.name);
+        }
+        tx.commit().map_err(sqlite_error)?;
+        Ok(updated)
+    })
+}
+
+/// Transcribes memos with empty content that have a file, one by one, with the command
+/// in `MEMOS_TRANSCRIBE_COMMAND`. Each transcript is saved as soon as it's ready, unless
+/// the memo got content in the meantime (then it's reported as failed), and
+/// `transcribe-progress` is emitted after every memo. Failures are collected rather than
+/// stopping the rest. Runs off the main thread, and can be stopped with
+/// [`cancel_operation`].
+#[tauri::command(async)]
+fn transcribe_all(
+    window: tauri::Window,
+    state: tauri::State<'_, State>,
+) -> Result<BatchResult, Error> {
+    state.run_command("transcribe_all", || transcribe_memos(&window, &state))
+}
+
+/// The body of [`transcribe_all`].
+fn transcribe_memos(window: &tauri::Window, state: &State) -> Result<BatchResult, Error> {
+    state.check_writable()?;
+    let command = state
+        .config
+        .read()
+        .map_err(tauri_error)?
+        .transcribe_command
+        .clone()
+        .ok_or_else(|| tauri_error("MEMOS_TRANSCRIBE_COMMAND not set"))?;
+    let dir = state.storage_dir()?;
+    let names: Vec<String> = {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        query_rows(&guard, "trim(content) = '' ORDER BY name ASC", ())?
+            .into_iter()
+            .map(|row| row.name)
+            .filter(|name| storage::resolve(&dir, name).is_file())
+            .collect()
+    };
+    state.begin_operation();
+    let mut result = BatchResult::default();
+    for (i, name) in names.iter().enumerate() {
+        if state.is_cancelled() {
+            result.cancelled_after = Some(i);
+            break;
+        }
+        let saved = audio::transcribe(&command, &storage::resolve(&dir, name))
+            .map_err(Error::Message)
+            .and_then(|text| {
+                let db_conn = state.db_conn.clone();
+                let guard = db_conn.lock().map_err(tauri_error)?;
+                retry_busy(|| {
+                    guard
+                        .execute(
+                            "UPDATE memos SET content = ?1, \
+                             updated_at = datetime('now'), content_hash = ?3 \
+                             WHERE name = ?2 AND trim(content) = ''",
+                            [&text, name, &db::content_hash(&text)],
+                        )
+                        .map_err(sqlite_error)
+                })
+            });
+        match saved {
+            Ok(0) => result.failed.push(Failure {
+                name: name.clone(),
+                reason: "Got content while it was being transcribed, so the transcript \
+                         wasn't saved"
+                    .to_string(),
+            }),
+            Ok(_) => result.succeeded.push(name.clone()),
+            Err(err) => result.failed.push(Failure {
+                name: name.clone(),
+                reason: err.to_string(),
+            }),
+        }
+        let progress = TranscribeProgress {
+            name: name.clone(),
+            done: i + 1,
+            total: names.len(),
+        };
+        if let Err(err) = window.emit("transcribe-progress", progress) {
+            eprintln!("Couldn't emit transcription progress: {err}");
+        }
+    }
+    Ok(result)
+}
+
+/// Asks the running long operation, like [`transcribe_all`] or [`export_audio_zip`], to
+/// stop after the memo it's working on. It then returns how far it got.
+#[tauri::command]
+fn cancel_operation(state: tauri::State<State>) -> Result<(), Error> {
+    state.run_command("cancel_operation", || {
+        state.cancel.store(true, Ordering::SeqCst);
+        Ok(())
+    })
+}
+
+/// Jaccard similarity of the sets of words (ignoring case) in two texts, from 0 for no
+/// common words to 1 for the same words. Two texts without words are the same.
+fn word_similarity(a: &str, b: &str) -> f64 {
+    let word_set =
+        |text: &str| words(text).map(str::to_lowercase).collect::<HashSet<_>>();
+    let (a, b) = (word_set(a), word_set(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// Scores how similar the contents of two memos are, from 0 to 1, by the words they share
+#[tauri::command]
+fn similarity(
+    name_a: &str,
+    name_b: &str,
+    state: tauri::State<State>,
+) -> Result<f64, Error> {
+    state.run_command("similarity", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let a = select_row(&guard, name_a)?;
+        let b = select_row(&guard, name_b)?;
+        Ok(word_similarity(&a.content, &b.content))
+    })
+}
+
+/// Returns other memos whose [`similarity`] to the memo is at least `threshold`, most
+/// similar first, as merge candidates
+#[tauri::command]
+fn similar_to(
+    name: &str,
+    threshold: f64,
+    state: tauri::State<State>,
+) -> Result<Vec<(String, f64)>, Error> {
+    state.run_command("similar_to", || {
+        let db_conn = state.db_conn.clone();
+        let guard = db_conn.lock().map_err(tauri_error)?;
+        let row = select_row(&guard, name)?;
+        let mut similar: Vec<(String, f64)> = query_rows(&guard, "name != ?1", [name])?
+            .into_iter()
+            .map(|other| {
+                let score = word_similarity(&row.content, &other.content);
+                (other.name, score)
+            })
+            .filter(|(_, score)| *score >= threshold)
+            .collect();
+        similar.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(similar)
+    })
+}
+
+/// Stops the players started by `open` that are still running, and returns how many
+/// there were. Players started through `open -a` (QuickTime and the system default)
+/// can't be stopped this way.
+#[tauri::command]
+fn stop_playback(state: tauri::State<State>) -> Result<usize, Error> {
+    state.run_command("stop_playback", || {
+        let mut players = state.players.lock().map_err(tauri_error)?;
+        let mut stopped = 0;
+        for mut player in players.drain(..) {
+            if matches!(player.try_wait(), Ok(None)) && player.kill().is_ok() {
+                let _ = player.wait();
+                stopped += 1;
+            }
+        }
+        Ok(stopped)
+    })
+}
+
+/// Whether any of the players started by `open` are still running
+#[tauri::command]
+fn is_playing(state: tauri::State<State>) -> Result<bool, Error> {
+    state.run_command("is_playing", || {
+        let mut players = state.players.lock().map_err(tauri_error)?;
+        reap_players(&mut players);
+        Ok(!players.is_empty())
+    })
 }
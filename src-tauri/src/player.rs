@@ -0,0 +1,78 @@
+//! Launching audio players.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Application used to play memos.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub enum Player {
+    #[default]
+    Vlc,
+    /// Whatever the OS opens audio files with.
+    SystemDefault,
+    QuickTime,
+    /// Path to an executable that accepts files as arguments.
+    Custom(String),
+}
+
+/// Playback settings that not every player supports.
+#[derive(Debug, Default)]
+pub struct PlayOptions {
+    /// Repeat the file instead of exiting after playing it. VLC only.
+    pub loop_playback: bool,
+    /// Where to start playing from. VLC only.
+    pub start_secs: Option<f64>,
+}
+
+impl Player {
+    /// Whether the player can only be launched on macOS.
+    pub fn is_macos_only(&self) -> bool {
+        !matches!(self, Player::Custom(_))
+    }
+
+    /// Checks that the player supports the given options.
+    pub fn check_options(&self, options: &PlayOptions) -> Result<(), String> {
+        if options.loop_playback && !matches!(self, Player::Vlc) {
+            return Err(String::from("Looping playback is only supported in VLC"));
+        }
+        if options.start_secs.is_some() && !matches!(self, Player::Vlc) {
+            return Err(String::from("Resuming playback is only supported in VLC"));
+        }
+        Ok(())
+    }
+
+    /// Builds the command that plays `files` (relative to `dir`) one after the other.
+    /// `vlc_path` is the VLC executable.
+    pub fn command(
+        &self,
+        vlc_path: &Path,
+        dir: &Path,
+        files: &[PathBuf],
+        options: &PlayOptions,
+    ) -> Command {
+        let mut command = match self {
+            Player::Vlc => {
+                let mut command = Command::new(vlc_path);
+                if options.loop_playback {
+                    command.arg("--loop");
+                } else {
+                    command.arg("--play-and-exit");
+                }
+                if let Some(secs) = options.start_secs {
+                    command.arg(format!("--start-time={secs}"));
+                }
+                command
+            }
+            Player::SystemDefault => Command::new("open"),
+            Player::QuickTime => {
+                let mut command = Command::new("open");
+                command.args(["-a", "QuickTime Player"]);
+                command
+            }
+            Player::Custom(path) => Command::new(path),
+        };
+        command.current_dir(dir).args(files);
+        command
+    }
+}
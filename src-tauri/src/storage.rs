@@ -0,0 +1,216 @@
+//! Helpers for locating memo audio files in the storage directory.
+//!
+//! File names are kept as `OsStr`/`PathBuf` here, since the storage directory can contain
+//! names that aren't valid UTF-8. They are only converted (lossily) for display.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Reads the directory with the audio files from `VOICE_MEMOS_STORAGE`. Commands get it
+/// from [`crate::config::Config`] instead, which can be reloaded.
+pub fn dir() -> Result<PathBuf, String> {
+    env::var_os("VOICE_MEMOS_STORAGE")
+        .map(PathBuf::from)
+        .ok_or_else(|| String::from("VOICE_MEMOS_STORAGE not set"))
+}
+
+/// Returns the path of the audio file for a memo.
+pub fn resolve(dir: &Path, name: impl AsRef<OsStr>) -> PathBuf {
+    dir.join(name.as_ref())
+}
+
+/// Returns the path of a memo's audio file: `moved_to` if [`move_file`] moved it out of
+/// the storage directory, or else the file in it.
+pub fn locate(dir: &Path, name: &str, moved_to: Option<&str>) -> PathBuf {
+    moved_to.map_or_else(|| resolve(dir, name), PathBuf::from)
+}
+
+/// Returns the names of a memo's audio files. A merged memo has no file of its own, but
+/// the files of the memos it was merged from (`merged_from`, which is comma-separated).
+pub fn memo_files<'a>(
+    name: &'a str,
+    merged_from: Option<&'a str>,
+) -> impl Iterator<Item = &'a str> {
+    merged_from.unwrap_or(name).split(',')
+}
+
+/// Converts a file name to a string for showing to the user.
+pub fn display_name(name: &OsStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+/// Lists names of the files in the storage directory, sorted. Hidden files (like
+/// `.DS_Store`) and subdirectories are skipped.
+pub fn file_names(dir: &Path) -> io::Result<Vec<OsString>> {
+    let mut names = Vec::new();
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        if !name.to_string_lossy().starts_with('.') {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Moves a file into `dest_dir`, keeping its name. Falls back to copying and deleting
+/// when `dest_dir` is on another volume; if deleting fails, the copy is removed again so
+/// the file stays only in its original place.
+pub fn move_file(from: &Path, dest_dir: &Path) -> io::Result<PathBuf> {
+    let name = from
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let to = dest_dir.join(name);
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    if fs::rename(from, &to).is_ok() {
+        return Ok(to);
+    }
+    if let Err(err) = fs::copy(from, &to).and_then(|_| fs::remove_file(from)) {
+        let _ = fs::remove_file(&to);
+        return Err(err);
+    }
+    Ok(to)
+}
+
+/// Renames files in `dir` by `(from, to)` pairs, skipping missing files. Goes through
+/// temporary names, so a `to` can be the `from` of another pair. If a rename fails, the
+/// files renamed so far are renamed back.
+pub fn rename_all(dir: &Path, pairs: &[(String, String)]) -> io::Result<()> {
+    let temp = |i: usize| dir.join(format!(".rename-{i}"));
+    let existing: Vec<_> = pairs
+        .iter()
+        .enumerate()
+        .filter(|(_, (from, _))| resolve(dir, from).is_file())
+        .collect();
+    let to_temp = existing
+        .iter()
+        .map(|(i, (from, _))| (resolve(dir, from), temp(*i)));
+    let from_temp = existing
+        .iter()
+        .map(|(i, (_, to))| (temp(*i), resolve(dir, to)));
+    let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (from, to) in to_temp.chain(from_temp) {
+        let result = if to.exists() {
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ))
+        } else {
+            fs::rename(&from, &to)
+        };
+        if let Err(err) = result {
+            for (from, to) in done.iter().rev() {
+                let _ = fs::rename(to, from);
+            }
+            return Err(err);
+        }
+        done.push((from, to));
+    }
+    Ok(())
+}
+
+/// Extensions of transcript files kept next to the audio files, in order of preference.
+const SIDECAR_EXTENSIONS: &[&str] = &["txt", "vtt"];
+
+/// Whether the file is a transcript rather than a memo's audio.
+pub fn is_sidecar(name: &OsStr) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| SIDECAR_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Reads the transcript next to a memo's audio file, i.e. `memo.txt` or `memo.vtt` for
+/// `memo.m4a`. For WebVTT files only the text of the cues is returned.
+pub fn read_sidecar(dir: &Path, name: impl AsRef<OsStr>) -> io::Result<Option<String>> {
+    let audio = resolve(dir, name);
+    for ext in SIDECAR_EXTENSIONS {
+        let path = audio.with_extension(ext);
+        if path == audio || !path.is_file() {
+            continue;
+        }
+        let text = fs::read_to_string(&path)?;
+        return Ok(Some(if *ext == "vtt" { vtt_text(&text) } else { text }));
+    }
+    Ok(None)
+}
+
+/// Drops the header, cue identifiers, timings and notes from WebVTT, keeping the text.
+fn vtt_text(vtt: &str) -> String {
+    let vtt = vtt.replace("\r\n", "\n");
+    let mut lines: Vec<&str> = Vec::new();
+    for block in vtt.split("\n\n") {
+        let block_lines: Vec<&str> = block.lines().map(str::trim_end).collect();
+        let Some(timing) = block_lines.iter().position(|line| line.contains("-->"))
+        else {
+            continue;
+        };
+        lines.extend(
+            block_lines[timing + 1..]
+                .iter()
+                .filter(|line| !line.is_empty()),
+        );
+    }
+    lines.join("\n")
+}
+
+/// Replaces a memo's file with `new_file`, keeping the original in the `originals`
+/// subdirectory as e.g. `memo (2).m4a` if `memo.m4a` is there already. Returns where the
+/// original is now.
+pub fn replace_keeping_original(
+    dir: &Path,
+    name: impl AsRef<OsStr>,
+    new_file: &Path,
+) -> io::Result<PathBuf> {
+    let path = resolve(dir, &name);
+    let originals = dir.join("originals");
+    fs::create_dir_all(&originals)?;
+    let stem = path.file_stem().unwrap_or(name.as_ref()).to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut backup = originals.join(name.as_ref());
+    for n in 2.. {
+        if !backup.exists() {
+            break;
+        }
+        backup = originals.join(format!("{stem} ({n}){extension}"));
+    }
+    fs::rename(&path, &backup)?;
+    if let Err(err) = fs::rename(new_file, &path) {
+        let _ = fs::rename(&backup, &path);
+        return Err(err);
+    }
+    Ok(backup)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn lists_names_that_are_not_utf8() {
+        let dir = env::temp_dir().join(format!("memos-storage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let name = OsStr::from_bytes(b"memo\xff.m4a");
+        fs::write(dir.join(name), b"").unwrap();
+        let names = file_names(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names.unwrap(), vec![name.to_os_string()]);
+        assert_eq!(display_name(name), "memo\u{FFFD}.m4a");
+    }
+}
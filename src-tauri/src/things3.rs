@@ -4,15 +4,37 @@
 
 use serde::{Serialize, Serializer};
 use serde_json::json;
+use url::Url;
 
 #[derive(Serialize)]
 pub struct Todo {
     pub title: String,
     pub notes: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(rename = "checklist-items", skip_serializing_if = "Vec::is_empty")]
+    pub checklist_items: Vec<ChecklistItem>,
+}
+
+#[derive(Serialize)]
+pub struct Project {
+    pub title: String,
+    pub notes: Option<String>,
+    pub items: Vec<Item>,
+}
+
+pub struct ChecklistItem {
+    pub title: String,
 }
 
 pub enum Item {
     Todo(Todo),
+    Project(Project),
+    /// Appends to the notes of an existing to-do. Needs an auth token in [`json_url`].
+    Update {
+        id: String,
+        append_notes: String,
+    },
 }
 
 impl Serialize for Item {
@@ -25,7 +47,84 @@ impl Serialize for Item {
                 "type": "to-do",
                 "attributes": &todo,
             }),
+            Item::Project(project) => json!({
+                "type": "project",
+                "attributes": &project,
+            }),
+            Item::Update { id, append_notes } => json!({
+                "type": "to-do",
+                "operation": "update",
+                "id": id,
+                "attributes": { "append-notes": append_notes },
+            }),
         };
         json.serialize(serializer)
     }
 }
+
+impl Serialize for ChecklistItem {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        json!({
+            "type": "checklist-item",
+            "attributes": { "title": &self.title },
+        })
+        .serialize(serializer)
+    }
+}
+
+/// Builds a `things:///json` URL that adds the given items.
+///
+/// Updates are only accepted with the auth token from Things settings → General →
+/// Enable Things URLs.
+pub fn json_url(
+    items: &[Item],
+    reveal: bool,
+    auth_token: Option<&str>,
+) -> Result<Url, serde_json::Error> {
+    let data = serde_json::to_string(items)?;
+    let mut url = Url::parse("things:///json").expect("valid URL");
+    url.query_pairs_mut()
+        .append_pair("data", &data)
+        .append_pair("reveal", &reveal.to_string());
+    if let Some(auth_token) = auth_token {
+        url.query_pairs_mut().append_pair("auth-token", auth_token);
+    }
+    // `query_pairs_mut` encodes spaces as `+`, which Things doesn't decode. Literal pluses
+    // are already escaped as `%2B`, so the remaining ones are all spaces.
+    let query = url.query().unwrap_or_default().replace('+', "%20");
+    url.set_query(Some(&query));
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_url_escapes_content() {
+        let items = [Item::Todo(Todo {
+            title: String::from("milk & eggs + café"),
+            notes: None,
+            tags: vec![],
+            checklist_items: vec![],
+        })];
+        let url = json_url(&items, true, None).unwrap();
+        let query = url.query().unwrap();
+        assert!(!query.contains('+'), "{query}");
+        assert!(
+            query.contains("milk%20%26%20eggs%20%2B%20caf%C3%A9"),
+            "{query}"
+        );
+        let data = url
+            .query_pairs()
+            .find(|(key, _)| key == "data")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(parsed[0]["attributes"]["title"], "milk & eggs + café");
+        assert!(query.ends_with("&reveal=true"), "{query}");
+    }
+}